@@ -0,0 +1,65 @@
+//! Shared plumbing for the workshop's checker binaries: every stage still
+//! prints its own line-oriented `tag,...` output for humans, but also emits a
+//! newline-delimited JSON record alongside it so the xtask harness has one
+//! stable contract to assert against regardless of which stage is running.
+
+use serde::Serialize;
+use std::time::Duration;
+
+/// The outcome of a single checker stage.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum CheckOutcome {
+    Pass,
+    Fail { reason: String },
+    Timeout,
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    stage: &'a str,
+    event: &'a str,
+    peer: Option<String>,
+    detail: Option<String>,
+    #[serde(flatten)]
+    outcome: Option<CheckOutcome>,
+}
+
+/// Install the tracing subscriber. Call once at the top of `main`.
+pub fn init(stage: &str) {
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .try_init();
+    tracing::info!(stage, "checker started");
+}
+
+/// Emit a structured JSON record for the harness, in addition to whatever
+/// plain `tag,...` line the stage already printed for humans.
+pub fn emit(stage: &str, event: &str, peer: Option<String>, detail: Option<String>, outcome: Option<CheckOutcome>) {
+    let record = Record {
+        stage,
+        event,
+        peer,
+        detail,
+        outcome,
+    };
+    match serde_json::to_string(&record) {
+        Ok(json) => tracing::info!(target: "checker_core::structured", "{json}"),
+        Err(error) => tracing::warn!("failed to serialize checker record: {error}"),
+    }
+}
+
+/// Run a stage's event loop with a hard ceiling so a stuck checker doesn't
+/// hang the workshop harness forever.
+pub async fn with_stage_timeout<F, T>(stage: &str, duration: Duration, fut: F) -> Result<T, CheckOutcome>
+where
+    F: std::future::Future<Output = T>,
+{
+    match tokio::time::timeout(duration, fut).await {
+        Ok(value) => Ok(value),
+        Err(_) => {
+            emit(stage, "timeout", None, None, Some(CheckOutcome::Timeout));
+            Err(CheckOutcome::Timeout)
+        }
+    }
+}
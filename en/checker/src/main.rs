@@ -1,22 +1,28 @@
 use anyhow::Result;
-use futures::StreamExt;
+use checker_core::CheckOutcome;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, StreamExt};
 use libp2p::{
+    connection_limits,
     core::transport::ListenerId,
-    gossipsub, identify, identity, kad,
+    dcutr, gossipsub, identify, identity, kad,
     multiaddr::Protocol,
-    noise, ping, tcp,
-    swarm::{NetworkBehaviour, SwarmEvent}, yamux,
-    Multiaddr, PeerId, StreamProtocol, SwarmBuilder,
+    noise, ping, relay, request_response, tcp,
+    swarm::{ConnectionId, NetworkBehaviour, SwarmEvent}, yamux,
+    Multiaddr, PeerId, StreamProtocol, Swarm, SwarmBuilder,
 };
+use libp2p_webrtc as webrtc;
 use prost::Message;
+use sha2::{Digest, Sha256};
 use std::{
-    collections::hash_map::DefaultHasher,
+    collections::{hash_map::DefaultHasher, HashMap},
     env,
     hash::{Hash, Hasher},
+    io,
     path::PathBuf,
     str::FromStr,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
+use tokio::io::AsyncBufReadExt;
 
 const IDENTIFY_PROTOCOL_VERSION: &str = "/ipfs/id/1.0.0";
 const AGENT_VERSION: &str = "universal-connectivity/0.1.0";
@@ -28,7 +34,34 @@ const GOSSIPSUB_TOPICS: &[&str] = &[
 const KADEMLIA_PROTOCOL_NAME: StreamProtocol = StreamProtocol::new("/ipfs/kad/1.0.0");
 const KADEMLIA_QUERY_TIMEOUT: u64 = 10;
 const KADEMLIA_BOOTSTRAP_INTERVAL: u64 = 300;
+// re-announce provider records well before the default 48h record TTL expires
+const KADEMLIA_PROVIDER_PUBLICATION_INTERVAL: u64 = 3600;
+const FILE_PROTOCOL_NAME: StreamProtocol = StreamProtocol::new("/universal-connectivity/file/1.0.0");
+// keeps a single file response from ever needing one giant in-memory frame
+const FILE_CHUNK_SIZE: usize = 16 * 1024;
 const TICK_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(5);
+// reputation deltas applied as peers behave (or misbehave) on the wire
+const REPUTATION_PING_OK: i32 = 1;
+const REPUTATION_PING_FAILURE: i32 = -5;
+const REPUTATION_IDENTIFY_OK: i32 = 2;
+const REPUTATION_IDENTIFY_ERROR: i32 = -5;
+const REPUTATION_CONNECTION_ERROR: i32 = -5;
+const REPUTATION_GOSSIP_OK: i32 = 1;
+// peers below this score get disconnected and temporarily banned, unless reserved
+const REPUTATION_BAN_THRESHOLD: i32 = -20;
+const REPUTATION_BAN_DURATION: Duration = Duration::from_secs(300);
+const STAGE: &str = "full-node";
+
+// everything we've learned about a peer from identify/ping plus our running
+// opinion of its behaviour
+#[derive(Debug, Default)]
+struct PeerInfo {
+    reputation: i32,
+    agent_version: Option<String>,
+    protocols: Vec<StreamProtocol>,
+    observed_addr: Option<Multiaddr>,
+    last_rtt: Option<Duration>,
+}
 
 #[derive(Clone, PartialEq, prost::Message)]
 pub struct UniversalConnectivityMessage {
@@ -40,6 +73,23 @@ pub struct UniversalConnectivityMessage {
     pub timestamp: i64,
     #[prost(enumeration = "MessageType", tag = "4")]
     pub message_type: i32,
+    // only populated when message_type == File: a lightweight pointer to the
+    // content instead of the file bytes themselves
+    #[prost(string, tag = "5")]
+    pub file_name: String,
+    #[prost(uint64, tag = "6")]
+    pub file_size: u64,
+    #[prost(bytes = "vec", tag = "7")]
+    pub file_key: Vec<u8>,
+    // only populated when message_type == Webrtc: SDP/connection data exchanged
+    // during the browser peer discovery handshake
+    #[prost(string, tag = "8")]
+    pub webrtc_data: String,
+    // only populated when message_type == BrowserPeerDiscovery
+    #[prost(string, tag = "9")]
+    pub browser_peer_id: String,
+    #[prost(string, repeated, tag = "10")]
+    pub browser_multiaddrs: Vec<String>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
@@ -48,15 +98,104 @@ pub enum MessageType {
     Chat = 0,
     File = 1,
     BrowserPeerDiscovery = 2,
+    Webrtc = 3,
 }
 
-// Define a custom network behaviour that includes ping, identify, and gossipsub functionality
+// Define a custom network behaviour that includes ping, identify, gossipsub,
+// kademlia, and file-transfer functionality
 #[derive(NetworkBehaviour)]
 struct Behaviour {
     ping: ping::Behaviour,
     identify: identify::Behaviour,
     gossipsub: gossipsub::Behaviour,
     kademlia: kad::Behaviour<kad::store::MemoryStore>,
+    file_transfer: request_response::Behaviour<FileExchangeCodec>,
+    relay_client: relay::client::Behaviour,
+    dcutr: dcutr::Behaviour,
+    connection_limits: connection_limits::Behaviour,
+}
+
+#[derive(Clone, Debug)]
+struct FileRequest {
+    key: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+struct FileResponse {
+    data: Vec<u8>,
+}
+
+#[derive(Clone, Default)]
+struct FileExchangeCodec;
+
+#[async_trait::async_trait]
+impl request_response::Codec for FileExchangeCodec {
+    type Protocol = StreamProtocol;
+    type Request = FileRequest;
+    type Response = FileResponse;
+
+    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut len_buf = [0u8; 4];
+        io.read_exact(&mut len_buf).await?;
+        let mut key = vec![0u8; u32::from_be_bytes(len_buf) as usize];
+        io.read_exact(&mut key).await?;
+        Ok(FileRequest { key })
+    }
+
+    async fn read_response<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        // each chunk is length-prefixed; a zero-length chunk marks the end,
+        // so an empty file is just an immediate terminator
+        let mut data = Vec::new();
+        loop {
+            let mut len_buf = [0u8; 4];
+            io.read_exact(&mut len_buf).await?;
+            let len = u32::from_be_bytes(len_buf) as usize;
+            if len == 0 {
+                break;
+            }
+            let mut chunk = vec![0u8; len];
+            io.read_exact(&mut chunk).await?;
+            data.extend_from_slice(&chunk);
+        }
+        Ok(FileResponse { data })
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        FileRequest { key }: Self::Request,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        io.write_all(&(key.len() as u32).to_be_bytes()).await?;
+        io.write_all(&key).await?;
+        io.close().await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        FileResponse { data }: Self::Response,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        for chunk in data.chunks(FILE_CHUNK_SIZE) {
+            io.write_all(&(chunk.len() as u32).to_be_bytes()).await?;
+            io.write_all(chunk).await?;
+        }
+        io.write_all(&0u32.to_be_bytes()).await?;
+        io.close().await
+    }
 }
 
 async fn read_identity() -> Result<identity::Keypair> {
@@ -82,6 +221,12 @@ fn create_test_message(
         message: format!("Hello from {peer_id}! ({counter})"),
         timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64,
         message_type: MessageType::Chat as i32,
+        file_name: String::new(),
+        file_size: 0,
+        file_key: Vec::new(),
+        webrtc_data: String::new(),
+        browser_peer_id: String::new(),
+        browser_multiaddrs: Vec::new(),
     };
     Ok((topic, message))
 }
@@ -105,8 +250,74 @@ fn split_address(addr: Multiaddr) -> Option<(PeerId, Multiaddr)> {
     peer_id.map(|id| (id, base_addr))
 }
 
+// content key for a file is the sha256 digest of its bytes, used both as the
+// kademlia provider record key and as the `file_key` gossiped in `FileMessage`
+fn content_key(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(data).to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+// record key a peer's display name is published under, so other nodes can
+// resolve a human-readable name instead of showing a raw PeerId
+fn display_name_key(peer_id: &PeerId) -> kad::RecordKey {
+    kad::RecordKey::new(&format!("/display-name/{peer_id}"))
+}
+
+fn bump_reputation(peers: &mut HashMap<PeerId, PeerInfo>, peer: PeerId, delta: i32) -> i32 {
+    let info = peers.entry(peer).or_default();
+    info.reputation += delta;
+    info.reputation
+}
+
+// disconnect and temporarily ban a peer once its reputation drops too low,
+// unless it's one of our bootstrap/reserved anchors
+fn enforce_reputation(
+    swarm: &mut Swarm<Behaviour>,
+    reserved_peers: &std::collections::HashSet<PeerId>,
+    banned: &mut HashMap<PeerId, Instant>,
+    peer: PeerId,
+    reputation: i32,
+) {
+    if reputation < REPUTATION_BAN_THRESHOLD && !reserved_peers.contains(&peer) {
+        println!("peerban,{peer},{reputation}");
+        banned.insert(peer, Instant::now());
+        let _ = swarm.disconnect_peer_id(peer);
+    }
+}
+
+// refuse a newly established connection once a non-reserved peer has more
+// than `max_established_per_peer` connections open; connection_limits has no
+// peer-allowlist concept, so bootstrap/reserved peers' exemption has to be
+// enforced here instead of by that behaviour
+fn enforce_connection_limit(
+    swarm: &mut Swarm<Behaviour>,
+    reserved_peers: &std::collections::HashSet<PeerId>,
+    established_per_peer: &HashMap<PeerId, u32>,
+    max_established_per_peer: u32,
+    peer: PeerId,
+    connection: ConnectionId,
+) {
+    let established = established_per_peer.get(&peer).copied().unwrap_or(0);
+    if established > max_established_per_peer && !reserved_peers.contains(&peer) {
+        println!("peerlimit,{peer},{established}");
+        swarm.close_connection(connection);
+    }
+}
+
+fn env_u32(name: &str, default: u32) -> u32 {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    checker_core::init(STAGE);
+
     // parse the remote peer addresses from the environment variable
     let mut listen_on: Vec<Multiaddr> = Vec::default();
     if let Ok(listen_addrs) = env::var("LISTEN_ADDRS") {
@@ -118,6 +329,17 @@ async fn main() -> Result<()> {
             .collect::<Result<Vec<_>, _>>()?; // Collect into Result and unwrap it
     }
 
+    // parse the relay circuit addresses to reserve a slot on, from the environment variable
+    let mut relay_addrs: Vec<Multiaddr> = Vec::default();
+    if let Ok(relay_peers) = env::var("RELAY_PEERS") {
+        relay_addrs = relay_peers
+            .split(',') // Split the string at ','
+            .map(str::trim) // Trim whitespace of each string
+            .filter(|s| !s.is_empty()) // Filter out empty strings
+            .map(Multiaddr::from_str) // Parse each string into Multiaddr
+            .collect::<Result<Vec<_>, _>>()?; // Collect into Result and unwrap it
+    }
+
     // parse the bootstrap peer addresses from the environment variable
     let mut bootstrap_addrs: Vec<Multiaddr> = Vec::default();
     if let Ok(bootstrap_peers) = env::var("BOOTSTRAP_PEERS") {
@@ -141,10 +363,11 @@ async fn main() -> Result<()> {
     let local_key = read_identity().await?;
     let local_peer_id = local_key.public().to_peer_id();
 
-    // Create a Gossipsub configuration
+    // Create a Gossipsub configuration. Strict validation means nothing is
+    // forwarded until we call report_message_validation_result ourselves.
     let gossipsub_config = gossipsub::ConfigBuilder::default()
         .heartbeat_interval(Duration::from_secs(10))
-        .validation_mode(gossipsub::ValidationMode::Permissive)
+        .validation_mode(gossipsub::ValidationMode::Strict)
         .message_id_fn(message_id)
         .mesh_outbound_min(1)
         .mesh_n_low(1)
@@ -158,6 +381,30 @@ async fn main() -> Result<()> {
     )
     .map_err(|e| anyhow::anyhow!(e))?;
 
+    // Prune misbehaving peers from the mesh by gossipsub's own peer score.
+    // Only the behaviour penalty is weighted here: no per-topic score params
+    // are set and no application score is ever supplied, so rejected
+    // messages, pings and connection errors do not move this score (they
+    // feed the separate per-peer reputation tracked in the event loop)
+    gossipsub
+        .with_peer_score(
+            gossipsub::PeerScoreParams {
+                topic_score_cap: 100.0,
+                app_specific_weight: 1.0,
+                behaviour_penalty_weight: -10.0,
+                behaviour_penalty_decay: 0.9,
+                ..Default::default()
+            },
+            gossipsub::PeerScoreThresholds {
+                gossip_threshold: -10.0,
+                publish_threshold: -50.0,
+                graylist_threshold: -80.0,
+                accept_px_threshold: 10.0,
+                opportunistic_graft_threshold: 20.0,
+            },
+        )
+        .map_err(|e| anyhow::anyhow!(e))?;
+
     // Subscribe to topics
     for topic in GOSSIPSUB_TOPICS {
         let topic = gossipsub::IdentTopic::new(*topic);
@@ -169,12 +416,40 @@ async fn main() -> Result<()> {
     kad_config.set_query_timeout(Duration::from_secs(KADEMLIA_QUERY_TIMEOUT));
     kad_config
         .set_periodic_bootstrap_interval(Some(Duration::from_secs(KADEMLIA_BOOTSTRAP_INTERVAL)));
+    kad_config.set_provider_publication_interval(Some(Duration::from_secs(
+        KADEMLIA_PROVIDER_PUBLICATION_INTERVAL,
+    )));
+    // re-put our own records (e.g. the display name record below) before
+    // their TTL expires, same reasoning as provider republication
+    kad_config.set_publication_interval(Some(Duration::from_secs(
+        KADEMLIA_PROVIDER_PUBLICATION_INTERVAL,
+    )));
 
     // Create Kademlia behavior with memory store
     let store = kad::store::MemoryStore::new(local_peer_id);
     let mut kademlia = kad::Behaviour::with_config(local_peer_id, store, kad_config);
     kademlia.set_mode(Some(kad::Mode::Server));
 
+    // Create the file-transfer request/response behaviour
+    let file_transfer = request_response::Behaviour::new(
+        [(FILE_PROTOCOL_NAME, request_response::ProtocolSupport::Full)],
+        request_response::Config::default(),
+    );
+
+    // Configurable connection bounds. connection_limits has no concept of a
+    // peer allowlist, so the per-peer cap is enforced by hand in the event
+    // loop (see enforce_connection_limit) where bootstrap/reserved peers
+    // (see `reserved_peers` below) can be exempted; only the peer-agnostic
+    // global bounds are delegated to connection_limits::Behaviour here.
+    let max_established_per_peer = env_u32("MAX_CONNECTIONS_PER_PEER", 4);
+    let connection_limits = connection_limits::Behaviour::new(
+        connection_limits::ConnectionLimits::default()
+            .with_max_pending_incoming(Some(env_u32("MAX_PENDING_INCOMING", 128)))
+            .with_max_pending_outgoing(Some(env_u32("MAX_PENDING_OUTGOING", 128)))
+            .with_max_established_incoming(Some(env_u32("MAX_ESTABLISHED_INCOMING", 256)))
+            .with_max_established_outgoing(Some(env_u32("MAX_ESTABLISHED_OUTGOING", 256))),
+    );
+
     let mut swarm = SwarmBuilder::with_existing_identity(local_key)
         .with_tokio()
         .with_tcp(
@@ -183,7 +458,14 @@ async fn main() -> Result<()> {
             yamux::Config::default,
         )?
         .with_quic()
-        .with_behaviour(|key| Behaviour {
+        .with_other_transport(|key| {
+            Ok(webrtc::tokio::Transport::new(
+                key.clone(),
+                webrtc::tokio::Certificate::generate(&mut rand::thread_rng())?,
+            ))
+        })?
+        .with_relay_client(noise::Config::new, yamux::Config::default)?
+        .with_behaviour(|key, relay_client| Behaviour {
             ping: ping::Behaviour::new(
                 ping::Config::new()
                     .with_interval(Duration::from_secs(1))
@@ -195,6 +477,10 @@ async fn main() -> Result<()> {
             ),
             gossipsub,
             kademlia,
+            file_transfer,
+            relay_client,
+            dcutr: dcutr::Behaviour::new(key.public().to_peer_id()),
+            connection_limits,
         })?
         .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
         .build();
@@ -207,10 +493,40 @@ async fn main() -> Result<()> {
         }
     }
 
+    // reserve a slot on each configured relay so we become reachable at
+    // `<relay_addr>/p2p-circuit` even when behind a NAT
+    for addr in relay_addrs.into_iter() {
+        if let Ok(listener_id) = swarm.listen_on(addr.with(Protocol::P2pCircuit)) {
+            listeners.push(listener_id);
+        }
+    }
+
+    // listen for browsers over WebRTC-direct; identify will advertise the
+    // resulting certhash-bearing address to our peers
+    if let Ok(webrtc_port) = env::var("WEBRTC_PORT") {
+        let addr: Multiaddr = format!("/ip4/0.0.0.0/udp/{webrtc_port}/webrtc-direct").parse()?;
+        if let Ok(listener_id) = swarm.listen_on(addr) {
+            listeners.push(listener_id);
+        }
+    }
+
+    // bootstrap/remote peers are exempt from the per-peer connection cap and
+    // reputation bans so the node never evicts its own anchors; the global
+    // connection_limits bounds still apply to them
+    let mut reserved_peers: std::collections::HashSet<PeerId> = std::collections::HashSet::new();
+    if let Ok(reserved) = env::var("REMOTE_PEERS") {
+        for raw in reserved.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            if let Ok(peer_id) = PeerId::from_str(raw) {
+                reserved_peers.insert(peer_id);
+            }
+        }
+    }
+
     if !bootstrap_addrs.is_empty() {
         // Add the bootstrap peer addresses to the kademlia behaviour
         for addr in bootstrap_addrs.into_iter() {
             if let Some((peer_id, peer_addr)) = split_address(addr) {
+                reserved_peers.insert(peer_id);
                 swarm
                     .behaviour_mut()
                     .kademlia
@@ -222,6 +538,77 @@ async fn main() -> Result<()> {
         swarm.behaviour_mut().kademlia.bootstrap()?;
     }
 
+    // files we are providing, keyed by content key, so we can answer requests
+    let mut shared_files: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+    // content key a pending GetProviders query is resolving, so we know what
+    // to request once a provider turns up
+    let mut pending_lookups: HashMap<kad::QueryId, Vec<u8>> = HashMap::new();
+    // gossipsub message ids we've already validated, to recognize duplicates
+    let mut seen_messages: std::collections::HashSet<gossipsub::MessageId> =
+        std::collections::HashSet::new();
+    // reputation and identify/ping info tracked per peer
+    let mut peers: HashMap<PeerId, PeerInfo> = HashMap::new();
+    // established connection count per peer, so enforce_connection_limit can
+    // refuse a non-reserved peer's surplus connections past
+    // max_established_per_peer
+    let mut established_per_peer: HashMap<PeerId, u32> = HashMap::new();
+    // peers temporarily banned for falling below the reputation threshold
+    let mut banned: HashMap<PeerId, Instant> = HashMap::new();
+    // display names resolved via `whois`, keyed by the peer they belong to
+    let mut known_names: HashMap<PeerId, String> = HashMap::new();
+    // peer a pending GetRecord `whois` lookup is resolving
+    let mut pending_records: HashMap<kad::QueryId, PeerId> = HashMap::new();
+    // raw key a pending generic `get` command is resolving
+    let mut pending_gets: HashMap<kad::QueryId, kad::RecordKey> = HashMap::new();
+
+    if let Ok(display_name) = env::var("DISPLAY_NAME") {
+        // republished automatically every `set_publication_interval` before
+        // the record's TTL expires, so peers can keep resolving us by name
+        swarm.behaviour_mut().kademlia.put_record(
+            kad::Record::new(display_name_key(&local_peer_id), display_name.clone().into_bytes()),
+            kad::Quorum::One,
+        )?;
+        println!("kademlia,putname,{display_name}");
+    }
+
+    // read `whois <peer_id>` commands from stdin to resolve display names
+    let mut stdin = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+
+    if let Ok(share_file) = env::var("SHARE_FILE") {
+        let data = tokio::fs::read(&share_file).await?;
+        let key = content_key(&data);
+        let name = PathBuf::from(&share_file)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or(share_file);
+        println!("file,announce,{name},{},{}", data.len(), hex(&key));
+
+        swarm
+            .behaviour_mut()
+            .kademlia
+            .start_providing(kad::RecordKey::new(&key))?;
+        shared_files.insert(key.clone(), data.clone());
+
+        let topic = gossipsub::IdentTopic::new("universal-connectivity-file");
+        let msg = UniversalConnectivityMessage {
+            from: local_peer_id.to_string(),
+            message: String::new(),
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64,
+            message_type: MessageType::File as i32,
+            file_name: name,
+            file_size: data.len() as u64,
+            file_key: key,
+            webrtc_data: String::new(),
+            browser_peer_id: String::new(),
+            browser_multiaddrs: Vec::new(),
+        };
+        let mut buf = Vec::new();
+        msg.encode(&mut buf)?;
+        if let Err(error) = swarm.behaviour_mut().gossipsub.publish(topic, buf) {
+            println!("error,{error}");
+        }
+    }
+
     // set up ticking timer
     let mut timer = tokio::time::interval(TICK_INTERVAL);
     let mut counter = 0;
@@ -232,174 +619,447 @@ async fn main() -> Result<()> {
 
     let mut shutdown = false;
 
-    'run: loop {
-        tokio::select! {
-            event = swarm.select_next_some() => match event {
-                SwarmEvent::ConnectionEstablished { peer_id, connection_id, endpoint, .. } => {
-                    println!("connected,{peer_id},{}", endpoint.get_remote_address());
-                    if close_after_connected {
-                        swarm.close_connection(connection_id);
-                    }
-                }
-                SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
-                    if let Some(error) = cause {
-                        println!("error,{error}");
-                    } else {
-                        println!("closed,{peer_id}");
-                    }
-                    if shutdown && swarm.network_info().num_peers() == 0 {
-                        println!("nomorepeers");
-                        break 'run Ok(());
-                    }
-                }
-                SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
-                    println!("incoming,{local_addr},{send_back_addr}");
-                }
-                SwarmEvent::OutgoingConnectionError { error, .. } => {
-                    println!("error,{error}");
-                }
-                SwarmEvent::Behaviour(behaviour_event) => match behaviour_event {
-                    BehaviourEvent::Ping(ping::Event { peer, connection, result}) => {
-                        match result {
-                            Ok(rtt) => {
-                                println!("ping,{peer},{} ms", rtt.as_millis());
-                                if close_after_ping {
-                                    swarm.close_connection(connection);
-                                }
-                            }
-                            Err(error) => {
-                                println!("error,{error}");
+    // hard ceiling on the whole node lifetime, so a run that never gets a
+    // SIGTERM/SIGQUIT (or a student that never drives it to one) can't hang
+    // the checker forever
+    let stage_timeout_secs = env_u32("STAGE_TIMEOUT_SECS", 600);
+    let outcome = checker_core::with_stage_timeout(
+        STAGE,
+        Duration::from_secs(stage_timeout_secs.into()),
+        async {
+            'run: loop {
+                tokio::select! {
+                    event = swarm.select_next_some() => match event {
+                        SwarmEvent::ConnectionEstablished { peer_id, connection_id, endpoint, .. } => {
+                            println!("connected,{peer_id},{}", endpoint.get_remote_address());
+                            let established = established_per_peer.entry(peer_id).or_insert(0);
+                            *established += 1;
+                            if banned.contains_key(&peer_id) && !reserved_peers.contains(&peer_id) {
+                                println!("peerban,{peer_id},reconnect refused");
+                                swarm.close_connection(connection_id);
+                            } else if close_after_connected {
+                                swarm.close_connection(connection_id);
+                            } else {
+                                enforce_connection_limit(&mut swarm, &reserved_peers, &established_per_peer, max_established_per_peer, peer_id, connection_id);
                             }
                         }
-                    }
-                    BehaviourEvent::Identify(identify_event) => {
-                        match identify_event {
-                            identify::Event::Received { peer_id, connection_id, info, .. } => {
-                                println!("identify,{peer_id},{}", info.agent_version);
-                                if close_after_identify {
-                                    swarm.close_connection(connection_id);
-                                }
+                        SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                            if let Some(established) = established_per_peer.get_mut(&peer_id) {
+                                *established = established.saturating_sub(1);
                             }
-                            identify::Event::Error { error, .. } => {
+                            if let Some(error) = cause {
                                 println!("error,{error}");
+                                let reputation = bump_reputation(&mut peers, peer_id, REPUTATION_CONNECTION_ERROR);
+                                enforce_reputation(&mut swarm, &reserved_peers, &mut banned, peer_id, reputation);
+                            } else {
+                                println!("closed,{peer_id}");
                             }
-                            _ => {}
+                            if shutdown && swarm.network_info().num_peers() == 0 {
+                                println!("nomorepeers");
+                                checker_core::emit(STAGE, "shutdown", None, None, Some(CheckOutcome::Pass));
+                                break 'run Ok(());
+                            }
+                        }
+                        SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
+                            println!("incoming,{local_addr},{send_back_addr}");
+                        }
+                        SwarmEvent::OutgoingConnectionError { error, .. } => {
+                            println!("error,{error}");
                         }
-                    }
-                    BehaviourEvent::Gossipsub(gossipsub_event) => {
-                        match gossipsub_event {
-                            gossipsub::Event::Message { message, .. } => {
-                                if let Ok(msg) = UniversalConnectivityMessage::decode(&message.data[..]) {
-                                    println!("msg,{},{},{}",
-                                        msg.from,
-                                        message.topic,
-                                        msg.message);
-
-                                    if close_after_gossip_msg {
-                                        if let Ok(peer_id) = PeerId::from_str(&msg.from) {
-                                            let _ = swarm.disconnect_peer_id(peer_id);
+                        SwarmEvent::Behaviour(behaviour_event) => match behaviour_event {
+                            BehaviourEvent::Ping(ping::Event { peer, connection, result}) => {
+                                match result {
+                                    Ok(rtt) => {
+                                        println!("ping,{peer},{} ms", rtt.as_millis());
+                                        if let Some(info) = peers.get_mut(&peer) {
+                                            info.last_rtt = Some(rtt);
+                                        }
+                                        bump_reputation(&mut peers, peer, REPUTATION_PING_OK);
+                                        if close_after_ping {
+                                            swarm.close_connection(connection);
                                         }
                                     }
-                                } else {
-                                    println!("error,{}", message.topic);
+                                    Err(error) => {
+                                        println!("error,{error}");
+                                        let reputation = bump_reputation(&mut peers, peer, REPUTATION_PING_FAILURE);
+                                        enforce_reputation(&mut swarm, &reserved_peers, &mut banned, peer, reputation);
+                                    }
                                 }
                             }
-                            gossipsub::Event::Subscribed { peer_id, topic } => {
-                                println!("subscribe,{peer_id},{topic}");
+                            BehaviourEvent::Identify(identify_event) => {
+                                match identify_event {
+                                    identify::Event::Received { peer_id, connection_id, info, .. } => {
+                                        println!("identify,{peer_id},{}", info.agent_version);
+                                        {
+                                            let entry = peers.entry(peer_id).or_default();
+                                            entry.agent_version = Some(info.agent_version.clone());
+                                            entry.protocols = info.protocols.clone();
+                                            entry.observed_addr = Some(info.observed_addr.clone());
+                                        }
+                                        bump_reputation(&mut peers, peer_id, REPUTATION_IDENTIFY_OK);
+                                        if close_after_identify {
+                                            swarm.close_connection(connection_id);
+                                        }
+                                    }
+                                    identify::Event::Error { peer_id, error, .. } => {
+                                        println!("error,{error}");
+                                        let reputation = bump_reputation(&mut peers, peer_id, REPUTATION_IDENTIFY_ERROR);
+                                        enforce_reputation(&mut swarm, &reserved_peers, &mut banned, peer_id, reputation);
+                                    }
+                                    _ => {}
+                                }
                             }
-                            gossipsub::Event::Unsubscribed { peer_id, topic } => {
-                                println!("unsubscribe,{peer_id},{topic}");
+                            BehaviourEvent::Gossipsub(gossipsub_event) => {
+                                match gossipsub_event {
+                                    gossipsub::Event::Message { propagation_source, message_id, message } => {
+                                        let acceptance = if !seen_messages.insert(message_id.clone()) {
+                                            gossipsub::MessageAcceptance::Ignore
+                                        } else if let Ok(msg) = UniversalConnectivityMessage::decode(&message.data[..]) {
+                                            bump_reputation(&mut peers, propagation_source, REPUTATION_GOSSIP_OK);
+
+                                            println!("msg,{},{},{}",
+                                                msg.from,
+                                                message.topic,
+                                                msg.message);
+
+                                            if msg.message_type == MessageType::File as i32 && !msg.file_key.is_empty() {
+                                                println!("file,offered,{},{},{}", msg.file_name, msg.file_size, hex(&msg.file_key));
+                                                let query_id = swarm
+                                                    .behaviour_mut()
+                                                    .kademlia
+                                                    .get_providers(kad::RecordKey::new(&msg.file_key));
+                                                pending_lookups.insert(query_id, msg.file_key);
+                                            }
+
+                                            if msg.message_type == MessageType::BrowserPeerDiscovery as i32
+                                                && !msg.browser_peer_id.is_empty()
+                                            {
+                                                // a browser peer cannot run Kademlia or be dialed directly;
+                                                // relay its PeerId/multiaddrs so we can dial it ourselves
+                                                println!(
+                                                    "browserdiscovery,{},{}",
+                                                    msg.browser_peer_id,
+                                                    msg.browser_multiaddrs.join(" ")
+                                                );
+                                                if let Ok(browser_peer) = PeerId::from_str(&msg.browser_peer_id) {
+                                                    for raw_addr in &msg.browser_multiaddrs {
+                                                        if let Ok(addr) = Multiaddr::from_str(raw_addr) {
+                                                            swarm
+                                                                .behaviour_mut()
+                                                                .kademlia
+                                                                .add_address(&browser_peer, addr.clone());
+                                                            let _ = swarm.dial(addr);
+                                                        }
+                                                    }
+                                                }
+                                            }
+
+                                            if msg.message_type == MessageType::Webrtc as i32 && !msg.webrtc_data.is_empty() {
+                                                // SDP/connection data exchanged during the browser peer
+                                                // discovery handshake
+                                                println!("webrtc,{},{}", msg.from, msg.webrtc_data);
+                                            }
+
+                                            if close_after_gossip_msg {
+                                                if let Ok(peer_id) = PeerId::from_str(&msg.from) {
+                                                    let _ = swarm.disconnect_peer_id(peer_id);
+                                                }
+                                            }
+
+                                            gossipsub::MessageAcceptance::Accept
+                                        } else {
+                                            // undecodable payload: reject so gossipsub drops it
+                                            // instead of forwarding it; with no per-topic score
+                                            // params set, this does not lower the source's score
+                                            println!("error,{}", message.topic);
+                                            gossipsub::MessageAcceptance::Reject
+                                        };
+
+                                        swarm.behaviour_mut().gossipsub.report_message_validation_result(
+                                            &message_id,
+                                            &propagation_source,
+                                            acceptance,
+                                        );
+                                    }
+                                    gossipsub::Event::Subscribed { peer_id, topic } => {
+                                        println!("subscribe,{peer_id},{topic}");
+                                    }
+                                    gossipsub::Event::Unsubscribed { peer_id, topic } => {
+                                        println!("unsubscribe,{peer_id},{topic}");
+                                    }
+                                    _ => {}
+                                }
                             }
-                            _ => {}
-                        }
-                    }
-                    BehaviourEvent::Kademlia(kad_event) => {
-                        match kad_event {
-                            kad::Event::OutboundQueryProgressed { result, .. } => {
-                                match result {
-                                    kad::QueryResult::Bootstrap(Ok(kad::BootstrapOk {
-                                        num_remaining, ..
-                                    })) => {
-                                        if num_remaining == 0 {
-                                            println!("kademlia,bootstrap");
-                                            if close_after_kademlia_bootstrap {
-                                                break 'run Ok(());
+                            BehaviourEvent::Kademlia(kad_event) => {
+                                match kad_event {
+                                    kad::Event::OutboundQueryProgressed { id, result, .. } => {
+                                        match result {
+                                            kad::QueryResult::Bootstrap(Ok(kad::BootstrapOk {
+                                                num_remaining, ..
+                                            })) => {
+                                                if num_remaining == 0 {
+                                                    println!("kademlia,bootstrap");
+                                                    if close_after_kademlia_bootstrap {
+                                                        break 'run Ok(());
+                                                    }
+                                                }
+                                            }
+                                            kad::QueryResult::Bootstrap(Err(kad::BootstrapError::Timeout { .. })) => {
+                                                println!("error,bootstrap timed out");
+                                            }
+                                            kad::QueryResult::GetClosestPeers(Ok(kad::GetClosestPeersOk { peers, .. })) => {
+                                                println!("kademlia,closestpeers,{}", peers.len());
+                                                for peer in &peers {
+                                                    let mut out = format!("closestpeer,{}", peer.peer_id);
+                                                    for addr in &peer.addrs {
+                                                        out = format!("{out},{addr}");
+                                                    }
+                                                    println!("{out}");
+                                                }
+                                            }
+                                            kad::QueryResult::GetClosestPeers(Err(kad::GetClosestPeersError::Timeout { .. })) => {
+                                                println!("error,get closest peers timed out");
+                                            }
+                                            kad::QueryResult::StartProviding(Ok(kad::AddProviderOk { key })) => {
+                                                println!("kademlia,providing,{}", hex(key.as_ref()));
+                                            }
+                                            kad::QueryResult::StartProviding(Err(error)) => {
+                                                println!("error,{error}");
+                                            }
+                                            kad::QueryResult::GetProviders(Ok(kad::GetProvidersOk::FoundProviders { key, providers })) => {
+                                                println!("kademlia,providers,{},{}", hex(key.as_ref()), providers.len());
+                                                if let Some(file_key) = pending_lookups.remove(&id) {
+                                                    if let Some(provider) = providers.into_iter().next() {
+                                                        swarm
+                                                            .behaviour_mut()
+                                                            .file_transfer
+                                                            .send_request(&provider, FileRequest { key: file_key });
+                                                    } else {
+                                                        println!("error,no providers for {}", hex(key.as_ref()));
+                                                    }
+                                                }
                                             }
+                                            kad::QueryResult::GetProviders(Ok(kad::GetProvidersOk::FinishedWithNoAdditionalRecord { .. })) => {
+                                                // when zero providers exist, this is the only event
+                                                // delivered for the query (no prior FoundProviders), so
+                                                // a still-pending lookup here means none were ever found
+                                                if let Some(file_key) = pending_lookups.remove(&id) {
+                                                    println!("error,no providers for {}", hex(&file_key));
+                                                }
+                                            }
+                                            kad::QueryResult::GetProviders(Err(error)) => {
+                                                println!("error,{error}");
+                                                pending_lookups.remove(&id);
+                                            }
+                                            kad::QueryResult::PutRecord(Ok(kad::PutRecordOk { key })) => {
+                                                println!("kademlia,putrecord,{}", hex(key.as_ref()));
+                                            }
+                                            kad::QueryResult::PutRecord(Err(error)) => {
+                                                println!("error,{error}");
+                                            }
+                                            kad::QueryResult::GetRecord(Ok(kad::GetRecordOk::FoundRecord(kad::PeerRecord {
+                                                record, ..
+                                            }))) => {
+                                                if let Some(peer) = pending_records.remove(&id) {
+                                                    let name = String::from_utf8_lossy(&record.value).into_owned();
+                                                    println!("kademlia,getrecord,{peer},{name}");
+                                                    known_names.insert(peer, name);
+                                                } else {
+                                                    let key = pending_gets.remove(&id).unwrap_or(record.key.clone());
+                                                    println!(
+                                                        "kademlia,get,{},{}",
+                                                        hex(key.as_ref()),
+                                                        String::from_utf8_lossy(&record.value)
+                                                    );
+                                                }
+                                            }
+                                            kad::QueryResult::GetRecord(Ok(kad::GetRecordOk::FinishedWithNoAdditionalRecord { .. })) => {
+                                                // query completed with no new records discovered beyond
+                                                // what FoundRecord already reported; nothing to do here
+                                            }
+                                            kad::QueryResult::GetRecord(Err(error)) => {
+                                                pending_records.remove(&id);
+                                                if let Some(key) = pending_gets.remove(&id) {
+                                                    println!("error,get {} failed: {error}", hex(key.as_ref()));
+                                                } else {
+                                                    println!("error,{error}");
+                                                }
+                                            }
+                                            _ => {}
+                                        }
+                                    }
+                                    kad::Event::RoutingUpdated { peer, is_new_peer, old_peer, .. } => {
+                                        let mut out = "kademlia,routing_update".to_string();
+                                        if is_new_peer {
+                                            out = format!("{out},new {peer}");
                                         }
+                                        if let Some(old) = old_peer {
+                                            out = format!("{out},replaced {old}")
+                                        }
+                                        println!("{out}");
+                                    }
+                                    kad::Event::UnroutablePeer { peer } => {
+                                        println!("kademlia,unroutable {peer}");
                                     }
-                                    kad::QueryResult::Bootstrap(Err(kad::BootstrapError::Timeout { .. })) => {
-                                        println!("error,bootstrap timed out");
+                                    kad::Event::RoutablePeer { peer, address } => {
+                                        println!("kademlia,routable,{peer},{address}");
                                     }
-                                    kad::QueryResult::GetClosestPeers(Ok(kad::GetClosestPeersOk { peers, .. })) => {
-                                        println!("kademlia,closestpeers,{}", peers.len());
-                                        for peer in &peers {
-                                            let mut out = format!("closestpeer,{}", peer.peer_id);
-                                            for addr in &peer.addrs {
-                                                out = format!("{out},{addr}");
+                                    _ => {}
+                                }
+                            }
+                            BehaviourEvent::FileTransfer(request_response_event) => {
+                                match request_response_event {
+                                    request_response::Event::Message { peer, message, .. } => {
+                                        match message {
+                                            request_response::Message::Request { request, channel, .. } => {
+                                                if let Some(data) = shared_files.get(&request.key) {
+                                                    let _ = swarm.behaviour_mut().file_transfer.send_response(
+                                                        channel,
+                                                        FileResponse { data: data.clone() },
+                                                    );
+                                                } else {
+                                                    println!("error,no such file {}", hex(&request.key));
+                                                }
+                                            }
+                                            request_response::Message::Response { response, .. } => {
+                                                println!("filerecv,{peer},{}", response.data.len());
                                             }
-                                            println!("{out}");
                                         }
                                     }
-                                    kad::QueryResult::GetClosestPeers(Err(kad::GetClosestPeersError::Timeout { .. })) => {
-                                        println!("error,get closest peers timed out");
+                                    request_response::Event::OutboundFailure { peer, error, .. } => {
+                                        println!("error,file transfer to {peer} failed: {error}");
+                                    }
+                                    request_response::Event::InboundFailure { peer, error, .. } => {
+                                        println!("error,file transfer from {peer} failed: {error}");
                                     }
                                     _ => {}
                                 }
                             }
-                            kad::Event::RoutingUpdated { peer, is_new_peer, old_peer, .. } => {
-                                let mut out = "kademlia,routing_update".to_string();
-                                if is_new_peer {
-                                    out = format!("{out},new {peer}");
+                            BehaviourEvent::RelayClient(relay_event) => {
+                                match relay_event {
+                                    relay::client::Event::ReservationReqAccepted { relay_peer_id, .. } => {
+                                        println!("relay,reservation,{relay_peer_id}");
+                                    }
+                                    relay::client::Event::OutboundCircuitEstablished { relay_peer_id, .. } => {
+                                        println!("relay,circuit,{relay_peer_id}");
+                                    }
+                                    _ => {}
+                                }
+                            }
+                            BehaviourEvent::Dcutr(dcutr::Event { remote_peer_id, result }) => {
+                                match result {
+                                    Ok(connection_id) => {
+                                        println!("holepunch,{remote_peer_id},{connection_id}");
+                                    }
+                                    Err(error) => {
+                                        // direct upgrade failed; we simply stay on the relayed connection
+                                        println!("error,holepunch with {remote_peer_id} failed: {error}");
+                                    }
                                 }
-                                if let Some(old) = old_peer {
-                                    out = format!("{out},replaced {old}")
+                            }
+                            // connection_limits never emits an event; its enforcement shows up as
+                            // SwarmEvent::IncomingConnectionError / OutgoingConnectionError instead
+                            BehaviourEvent::ConnectionLimits(never) => match never {},
+                        }
+                        _ => {}
+                    },
+                    _ = timer.tick() => {
+                        if chatty {
+                            counter += 1;
+                            let (topic, msg) = create_test_message(&local_peer_id, counter)?;
+                            let mut buf = Vec::new();
+                            msg.encode(&mut buf)?;
+                            if let Err(error) = swarm.behaviour_mut().gossipsub.publish(topic, buf) {
+                                println!("error,{error}");
+                            }
+                        }
+
+                        // bans expire after REPUTATION_BAN_DURATION
+                        banned.retain(|_, banned_at| banned_at.elapsed() < REPUTATION_BAN_DURATION);
+
+                        if !peers.is_empty() {
+                            let best = peers.values().map(|p| p.reputation).max().unwrap_or(0);
+                            let worst = peers.values().map(|p| p.reputation).min().unwrap_or(0);
+                            println!("peers,{},{best},{worst}", peers.len());
+                        }
+                    },
+                    _ = sig_term.recv() => {
+                        // turn off our listeners
+                        for listener_id in &listeners {
+                            let _ = swarm.remove_listener(*listener_id);
+                        }
+
+                        // disconnect from all connected peers
+                        let connected: Vec<PeerId> = swarm.connected_peers().cloned().collect();
+                        for peer_id in &connected {
+                            let _ = swarm.disconnect_peer_id(*peer_id);
+                        }
+
+                        shutdown = true;
+                    },
+                    _ = sig_quit.recv() => {
+                        // received SIG_QUIT
+                        println!("sigquit");
+                        break 'run Ok(());
+                    },
+                    Ok(Some(line)) = stdin.next_line() => {
+                        let mut parts = line.split_whitespace();
+                        match (parts.next(), parts.next()) {
+                            (Some("whois"), Some(raw)) => {
+                                if let Ok(peer_id) = PeerId::from_str(raw) {
+                                    if let Some(name) = known_names.get(&peer_id) {
+                                        println!("kademlia,getrecord,{peer_id},{name}");
+                                    } else {
+                                        let query_id = swarm
+                                            .behaviour_mut()
+                                            .kademlia
+                                            .get_record(display_name_key(&peer_id));
+                                        pending_records.insert(query_id, peer_id);
+                                    }
+                                } else {
+                                    println!("error,invalid peer id {raw}");
                                 }
-                                println!("{out}");
                             }
-                            kad::Event::UnroutablePeer { peer } => {
-                                println!("kademlia,unroutable {peer}");
+                            // generic DHT command surface: `put <key> <value>`, `get <key>`,
+                            // `provide <key>`, for exercising PutRecord/GetRecord/StartProviding
+                            // directly rather than through the whois/file-sharing flows above
+                            (Some("put"), Some(key)) => {
+                                let key = key.to_string();
+                                let value = parts.collect::<Vec<_>>().join(" ");
+                                swarm.behaviour_mut().kademlia.put_record(
+                                    kad::Record::new(kad::RecordKey::new(&key), value.into_bytes()),
+                                    kad::Quorum::One,
+                                )?;
                             }
-                            kad::Event::RoutablePeer { peer, address } => {
-                                println!("kademlia,routable,{peer},{address}");
+                            (Some("get"), Some(key)) => {
+                                let record_key = kad::RecordKey::new(&key.to_string());
+                                let query_id = swarm.behaviour_mut().kademlia.get_record(record_key.clone());
+                                pending_gets.insert(query_id, record_key);
+                            }
+                            (Some("provide"), Some(key)) => {
+                                swarm
+                                    .behaviour_mut()
+                                    .kademlia
+                                    .start_providing(kad::RecordKey::new(&key.to_string()))?;
+                            }
+                            (Some("getproviders"), Some(key)) => {
+                                swarm
+                                    .behaviour_mut()
+                                    .kademlia
+                                    .get_providers(kad::RecordKey::new(&key.to_string()));
                             }
                             _ => {}
                         }
-                    }
-                }
-                _ => {}
-            },
-            _ = timer.tick() => {
-                if chatty {
-                    counter += 1;
-                    let (topic, msg) = create_test_message(&local_peer_id, counter)?;
-                    let mut buf = Vec::new();
-                    msg.encode(&mut buf)?;
-                    if let Err(error) = swarm.behaviour_mut().gossipsub.publish(topic, buf) {
-                        println!("error,{error}");
-                    }
-                }
-            },
-            _ = sig_term.recv() => {
-                // turn off our listeners
-                for listener_id in &listeners {
-                    let _ = swarm.remove_listener(*listener_id);
-                }
-
-                // disconnect from all connected peers
-                let connected: Vec<PeerId> = swarm.connected_peers().cloned().collect();
-                for peer_id in &connected {
-                    let _ = swarm.disconnect_peer_id(*peer_id);
+                    },
                 }
-
-                shutdown = true;
-            },
-            _ = sig_quit.recv() => {
-                // received SIG_QUIT
-                println!("sigquit");
-                break 'run Ok(());
-            },
-        }
-    }
+            }
+        },
+    )
+    .await;
 
     // fin
+    match outcome {
+        Ok(result) => result,
+        Err(_) => Ok(()),
+    }
 }
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use checker_core::CheckOutcome;
 use futures::StreamExt;
 use libp2p::{
     identity, noise, ping,
@@ -7,6 +8,11 @@ use libp2p::{
 };
 use std::{env, str::FromStr, time::Duration};
 
+const STAGE: &str = "tcp-transport";
+// hard ceiling on the whole stage event loop, so a student node that
+// never emits the expected terminal event can't hang the checker forever
+const STAGE_TIMEOUT_SECS: u64 = 60;
+
 // Define a custom network behaviour that includes ping functionality
 #[derive(NetworkBehaviour)]
 struct Behaviour {
@@ -15,6 +21,8 @@ struct Behaviour {
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    checker_core::init(STAGE);
+
     let remote_peers = env::var("REMOTE_PEERS")?;
     let remote_addrs: Vec<Multiaddr> = remote_peers
         .split(',')
@@ -43,26 +51,40 @@ async fn main() -> Result<()> {
         swarm.listen_on(addr)?;
     }
 
-    loop {
-        tokio::select! {
-            Some(event) = swarm.next() => match event {
-                SwarmEvent::ConnectionEstablished { peer_id, connection_id, endpoint, .. } => {
-                    println!("connected,{peer_id},{}", endpoint.get_remote_address());
-                    swarm.close_connection(connection_id);
-                }
-                SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
-                    if let Some(err) = cause {
-                        println!("error,{err}");
-                    } else {
-                        println!("closed,{peer_id}");
+    let outcome = checker_core::with_stage_timeout(
+        STAGE,
+        Duration::from_secs(STAGE_TIMEOUT_SECS),
+        async {
+            loop {
+                tokio::select! {
+                    Some(event) = swarm.next() => match event {
+                        SwarmEvent::ConnectionEstablished { peer_id, connection_id, endpoint, .. } => {
+                            println!("connected,{peer_id},{}", endpoint.get_remote_address());
+                            swarm.close_connection(connection_id);
+                        }
+                        SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                            if let Some(err) = cause {
+                                println!("error,{err}");
+                                checker_core::emit(STAGE, "closed", Some(peer_id.to_string()), Some(err.to_string()), Some(CheckOutcome::Fail { reason: err.to_string() }));
+                            } else {
+                                println!("closed,{peer_id}");
+                                checker_core::emit(STAGE, "closed", Some(peer_id.to_string()), None, Some(CheckOutcome::Pass));
+                            }
+                            return Ok(())
+                        }
+                        SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
+                            println!("incoming,{local_addr},{send_back_addr}");
+                        }
+                        _ => {}
                     }
-                    return Ok(())
                 }
-                SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
-                    println!("incoming,{local_addr},{send_back_addr}");
-                }
-                _ => {}
             }
-        }
+        },
+    )
+    .await;
+
+    match outcome {
+        Ok(result) => result,
+        Err(_) => Ok(()),
     }
 }
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use checker_core::CheckOutcome;
 use futures::StreamExt;
 use libp2p::{
     identity, noise, ping,
@@ -7,6 +8,11 @@ use libp2p::{
 };
 use std::{env, str::FromStr, time::Duration};
 
+const STAGE: &str = "ping-checkpoint";
+// hard ceiling on the whole stage event loop, so a student node that
+// never emits the expected terminal event can't hang the checker forever
+const STAGE_TIMEOUT_SECS: u64 = 60;
+
 // Define a custom network behaviour that includes ping functionality
 #[derive(NetworkBehaviour)]
 struct Behaviour {
@@ -15,6 +21,8 @@ struct Behaviour {
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    checker_core::init(STAGE);
+
     let remote_peers = env::var("REMOTE_PEERS")?;
     let remote_addrs: Vec<Multiaddr> = remote_peers
         .split(',')
@@ -47,41 +55,55 @@ async fn main() -> Result<()> {
         swarm.listen_on(addr)?;
     }
 
-    loop {
-        tokio::select! {
-            Some(event) = swarm.next() => match event {
-                SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
-                    println!("connected,{peer_id},{}", endpoint.get_remote_address());
-                }
-                SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
-                    if let Some(error) = cause {
-                        println!("error,{error}");
-                    } else {
-                        println!("closed,{peer_id}");
-                    }
-                    return Ok(())
-                }
-                SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
-                    println!("incoming,{local_addr},{send_back_addr}");
-                }
-                SwarmEvent::Behaviour(behaviour_event) => match behaviour_event {
-                    BehaviourEvent::Ping(ping::Event { peer, connection, result }) => {
-                        match result {
-                            Ok(rtt) => {
-                                println!("ping,{peer},{} ms", rtt.as_millis());
+    let outcome = checker_core::with_stage_timeout(
+        STAGE,
+        Duration::from_secs(STAGE_TIMEOUT_SECS),
+        async {
+            loop {
+                tokio::select! {
+                    Some(event) = swarm.next() => match event {
+                        SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                            println!("connected,{peer_id},{}", endpoint.get_remote_address());
+                        }
+                        SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                            if let Some(error) = cause {
+                                println!("error,{error}");
+                            } else {
+                                println!("closed,{peer_id}");
                             }
-                            Err(failure) => {
-                                println!("error,{failure}");
+                            return Ok(())
+                        }
+                        SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
+                            println!("incoming,{local_addr},{send_back_addr}");
+                        }
+                        SwarmEvent::Behaviour(behaviour_event) => match behaviour_event {
+                            BehaviourEvent::Ping(ping::Event { peer, connection, result }) => {
+                                match result {
+                                    Ok(rtt) => {
+                                        println!("ping,{peer},{} ms", rtt.as_millis());
+                                        checker_core::emit(STAGE, "ping", Some(peer.to_string()), Some(format!("{} ms", rtt.as_millis())), Some(CheckOutcome::Pass));
+                                    }
+                                    Err(failure) => {
+                                        println!("error,{failure}");
+                                        checker_core::emit(STAGE, "ping", Some(peer.to_string()), Some(failure.to_string()), Some(CheckOutcome::Fail { reason: failure.to_string() }));
+                                    }
+                                }
+                                swarm.close_connection(connection);
                             }
                         }
-                        swarm.close_connection(connection);
+                        SwarmEvent::OutgoingConnectionError { error, .. } => {
+                            println!("error,{error}");
+                        }
+                        _ => {}
                     }
                 }
-                SwarmEvent::OutgoingConnectionError { error, .. } => {
-                    println!("error,{error}");
-                }
-                _ => {}
             }
-        }
+        },
+    )
+    .await;
+
+    match outcome {
+        Ok(result) => result,
+        Err(_) => Ok(()),
     }
 }
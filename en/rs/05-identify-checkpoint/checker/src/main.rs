@@ -1,4 +1,5 @@
 use anyhow::Result;
+use checker_core::CheckOutcome;
 use futures::StreamExt;
 use libp2p::{
     identify, identity, ping,
@@ -9,6 +10,10 @@ use std::{env, str::FromStr, time::Duration};
 
 const IDENTIFY_PROTOCOL_VERSION: &str = "/ipfs/id/1.0.0";
 const AGENT_VERSION: &str = "universal-connectivity/0.1.0";
+const STAGE: &str = "identify-checkpoint";
+// hard ceiling on the whole stage event loop, so a student node that
+// never emits the expected terminal event can't hang the checker forever
+const STAGE_TIMEOUT_SECS: u64 = 60;
 
 // Define a custom network behaviour that includes ping and identify functionality
 #[derive(NetworkBehaviour)]
@@ -19,6 +24,8 @@ struct Behaviour {
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    checker_core::init(STAGE);
+
     let remote_peers = env::var("REMOTE_PEERS")?;
     let remote_addrs: Vec<Multiaddr> = remote_peers
         .split(',')
@@ -51,52 +58,66 @@ async fn main() -> Result<()> {
         swarm.listen_on(addr)?;
     }
 
-    loop {
-        tokio::select! {
-            Some(event) = swarm.next() => match event {
-                SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
-                    println!("connected,{peer_id},{}", endpoint.get_remote_address());
-                }
-                SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
-                    if let Some(error) = cause {
-                        println!("error,{error}");
-                    } else {
-                        println!("closed,{peer_id}");
-                    }
-                    return Ok(())
-                }
-                SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
-                    println!("incoming,{local_addr},{send_back_addr}");
-                }
-                SwarmEvent::Behaviour(behaviour_event) => match behaviour_event {
-                    BehaviourEvent::Ping(ping::Event { peer, result, .. }) => {
-                        match result {
-                            Ok(rtt) => {
-                                println!("ping,{peer},{} ms", rtt.as_millis());
-                            }
-                            Err(failure) => {
-                                println!("error,{failure}");
+    let outcome = checker_core::with_stage_timeout(
+        STAGE,
+        Duration::from_secs(STAGE_TIMEOUT_SECS),
+        async {
+            loop {
+                tokio::select! {
+                    Some(event) = swarm.next() => match event {
+                        SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                            println!("connected,{peer_id},{}", endpoint.get_remote_address());
+                        }
+                        SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                            if let Some(error) = cause {
+                                println!("error,{error}");
+                            } else {
+                                println!("closed,{peer_id}");
                             }
+                            return Ok(())
                         }
-                    }
-                    BehaviourEvent::Identify(identify_event) => {
-                        match identify_event {
-                            identify::Event::Received { peer_id, connection_id, info } => {
-                                println!("identify,{peer_id},{},{}", info.protocol_version, info.agent_version);
-                                swarm.close_connection(connection_id);
+                        SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
+                            println!("incoming,{local_addr},{send_back_addr}");
+                        }
+                        SwarmEvent::Behaviour(behaviour_event) => match behaviour_event {
+                            BehaviourEvent::Ping(ping::Event { peer, result, .. }) => {
+                                match result {
+                                    Ok(rtt) => {
+                                        println!("ping,{peer},{} ms", rtt.as_millis());
+                                    }
+                                    Err(failure) => {
+                                        println!("error,{failure}");
+                                    }
+                                }
                             }
-                            identify::Event::Error { error, .. } => {
-                                println!("error,{error}");
+                            BehaviourEvent::Identify(identify_event) => {
+                                match identify_event {
+                                    identify::Event::Received { peer_id, connection_id, info } => {
+                                        println!("identify,{peer_id},{},{}", info.protocol_version, info.agent_version);
+                                        checker_core::emit(STAGE, "identify", Some(peer_id.to_string()), Some(info.agent_version.clone()), Some(CheckOutcome::Pass));
+                                        swarm.close_connection(connection_id);
+                                    }
+                                    identify::Event::Error { error, .. } => {
+                                        println!("error,{error}");
+                                        checker_core::emit(STAGE, "identify", None, Some(error.to_string()), Some(CheckOutcome::Fail { reason: error.to_string() }));
+                                    }
+                                    _ => {}
+                                }
                             }
-                            _ => {}
                         }
+                        SwarmEvent::OutgoingConnectionError { error, .. } => {
+                            println!("error,{error}");
+                        }
+                        _ => {}
                     }
                 }
-                SwarmEvent::OutgoingConnectionError { error, .. } => {
-                    println!("error,{error}");
-                }
-                _ => {}
             }
-        }
+        },
+    )
+    .await;
+
+    match outcome {
+        Ok(result) => result,
+        Err(_) => Ok(()),
     }
 }
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use checker_core::CheckOutcome;
 use futures::StreamExt;
 use libp2p::{
     gossipsub, identify, identity, ping,
@@ -14,6 +15,11 @@ use std::{
     time::Duration,
 };
 
+const STAGE: &str = "gossipsub-checkpoint";
+// hard ceiling on the whole stage event loop, so a student node that
+// never emits the expected terminal event can't hang the checker forever
+const STAGE_TIMEOUT_SECS: u64 = 60;
+
 const IDENTIFY_PROTOCOL_VERSION: &str = "/ipfs/id/1.0.0";
 const AGENT_VERSION: &str = "universal-connectivity/0.1.0";
 const GOSSIPSUB_TOPICS: &[&str] = &[
@@ -58,6 +64,8 @@ fn message_id(msg: &gossipsub::Message) -> gossipsub::MessageId {
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    checker_core::init(STAGE);
+
     let remote_peers = env::var("REMOTE_PEERS")?;
     let remote_addrs: Vec<Multiaddr> = remote_peers
         .split(',')
@@ -116,76 +124,90 @@ async fn main() -> Result<()> {
 
     let mut cid: Option<ConnectionId> = None;
 
-    loop {
-        tokio::select! {
-            Some(event) = swarm.next() => match event {
-                SwarmEvent::ConnectionEstablished { peer_id, connection_id, endpoint, .. } => {
-                    println!("connected,{peer_id},{}", endpoint.get_remote_address());
-                    cid = Some(connection_id);
-                }
-                SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
-                    if let Some(error) = cause {
-                        println!("error,{error}");
-                    } else {
-                        println!("closed,{peer_id}");
-                    }
-                    return Ok(())
-                }
-                SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
-                    println!("incoming,{local_addr},{send_back_addr}");
-                }
-                SwarmEvent::OutgoingConnectionError { error, .. } => {
-                    println!("error,{error}");
-                }
-                SwarmEvent::Behaviour(behaviour_event) => match behaviour_event {
-                    BehaviourEvent::Ping(ping::Event { peer, result, .. }) => {
-                        match result {
-                            Ok(rtt) => {
-                                println!("ping,{peer},{} ms", rtt.as_millis());
-                            }
-                            Err(failure) => {
-                                println!("error,{failure}");
-                            }
+    let outcome = checker_core::with_stage_timeout(
+        STAGE,
+        Duration::from_secs(STAGE_TIMEOUT_SECS),
+        async {
+            loop {
+                tokio::select! {
+                    Some(event) = swarm.next() => match event {
+                        SwarmEvent::ConnectionEstablished { peer_id, connection_id, endpoint, .. } => {
+                            println!("connected,{peer_id},{}", endpoint.get_remote_address());
+                            cid = Some(connection_id);
                         }
-                    }
-                    BehaviourEvent::Identify(identify_event) => {
-                        match identify_event {
-                            identify::Event::Received { peer_id, info, .. } => {
-                                println!("identify,{peer_id},{},{}", info.protocol_version, info.agent_version);
-                            }
-                            identify::Event::Error { error, .. } => {
+                        SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                            if let Some(error) = cause {
                                 println!("error,{error}");
+                            } else {
+                                println!("closed,{peer_id}");
                             }
-                            _ => {}
+                            return Ok(())
                         }
-                    }
-                    BehaviourEvent::Gossipsub(gossipsub_event) => {
-                        match gossipsub_event {
-                            gossipsub::Event::Message { message, .. } => {
-                                if let Ok(msg) = UniversalConnectivityMessage::decode(&message.data[..]) {
-                                    println!("msg,{},{},{}",
-                                        msg.from,
-                                        message.topic,
-                                        msg.message);
-                                    if let Some(connection_id) = cid {
-                                        swarm.close_connection(connection_id);
+                        SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
+                            println!("incoming,{local_addr},{send_back_addr}");
+                        }
+                        SwarmEvent::OutgoingConnectionError { error, .. } => {
+                            println!("error,{error}");
+                        }
+                        SwarmEvent::Behaviour(behaviour_event) => match behaviour_event {
+                            BehaviourEvent::Ping(ping::Event { peer, result, .. }) => {
+                                match result {
+                                    Ok(rtt) => {
+                                        println!("ping,{peer},{} ms", rtt.as_millis());
+                                    }
+                                    Err(failure) => {
+                                        println!("error,{failure}");
                                     }
-                                } else {
-                                    println!("error,{}", message.topic);
                                 }
                             }
-                            gossipsub::Event::Subscribed { peer_id, topic } => {
-                                println!("subscribe,{peer_id},{topic}");
+                            BehaviourEvent::Identify(identify_event) => {
+                                match identify_event {
+                                    identify::Event::Received { peer_id, info, .. } => {
+                                        println!("identify,{peer_id},{},{}", info.protocol_version, info.agent_version);
+                                    }
+                                    identify::Event::Error { error, .. } => {
+                                        println!("error,{error}");
+                                    }
+                                    _ => {}
+                                }
                             }
-                            gossipsub::Event::Unsubscribed { peer_id, topic } => {
-                                println!("unsubscribe,{peer_id},{topic}");
+                            BehaviourEvent::Gossipsub(gossipsub_event) => {
+                                match gossipsub_event {
+                                    gossipsub::Event::Message { message, .. } => {
+                                        if let Ok(msg) = UniversalConnectivityMessage::decode(&message.data[..]) {
+                                            println!("msg,{},{},{}",
+                                                msg.from,
+                                                message.topic,
+                                                msg.message);
+                                            checker_core::emit(STAGE, "msg", Some(msg.from.clone()), Some(msg.message.clone()), Some(CheckOutcome::Pass));
+                                            if let Some(connection_id) = cid {
+                                                swarm.close_connection(connection_id);
+                                            }
+                                        } else {
+                                            println!("error,{}", message.topic);
+                                            checker_core::emit(STAGE, "msg", None, Some(format!("undecodable message on {}", message.topic)), Some(CheckOutcome::Fail { reason: "undecodable message".to_string() }));
+                                        }
+                                    }
+                                    gossipsub::Event::Subscribed { peer_id, topic } => {
+                                        println!("subscribe,{peer_id},{topic}");
+                                    }
+                                    gossipsub::Event::Unsubscribed { peer_id, topic } => {
+                                        println!("unsubscribe,{peer_id},{topic}");
+                                    }
+                                    _ => {}
+                                }
                             }
-                            _ => {}
                         }
+                        _ => {}
                     }
                 }
-                _ => {}
             }
-        }
+        },
+    )
+    .await;
+
+    match outcome {
+        Ok(result) => result,
+        Err(_) => Ok(()),
     }
 }
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use checker_core::CheckOutcome;
 use futures::StreamExt;
 use libp2p::{
     gossipsub, identify, identity, kad,
@@ -29,6 +30,10 @@ const GOSSIPSUB_TOPICS: &[&str] = &[
 const KADEMLIA_PROTOCOL_NAME: StreamProtocol = StreamProtocol::new("/ipfs/kad/1.0.0");
 const KADEMLIA_QUERY_TIMEOUT: u64 = 10;
 const KADEMLIA_BOOTSTRAP_INTERVAL: u64 = 300;
+const STAGE: &str = "kademlia-checkpoint";
+// hard ceiling on the whole stage event loop, so a student node that
+// never emits the expected terminal event can't hang the checker forever
+const STAGE_TIMEOUT_SECS: u64 = 60;
 
 #[derive(Clone, PartialEq, prost::Message)]
 pub struct UniversalConnectivityMessage {
@@ -106,6 +111,8 @@ fn split_address(addr: Multiaddr) -> Option<(PeerId, Multiaddr)> {
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    checker_core::init(STAGE);
+
     // parse the remote peer addresses from the environment variable
     let remote_peers = env::var("REMOTE_PEERS")?;
     let remote_addrs: Vec<Multiaddr> = remote_peers
@@ -196,121 +203,135 @@ async fn main() -> Result<()> {
     // Start the Kademlia bootstrap process
     swarm.behaviour_mut().kademlia.bootstrap()?;
 
-    loop {
-        tokio::select! {
-            Some(event) = swarm.next() => match event {
-                SwarmEvent::ConnectionEstablished { peer_id, connection_id, endpoint, .. } => {
-                    println!("connected,{peer_id},{}", endpoint.get_remote_address());
-                }
-                SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
-                    if let Some(error) = cause {
-                        println!("error,{error}");
-                    } else {
-                        println!("closed,{peer_id}");
-                    }
-                    return Ok(())
-                }
-                SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
-                    println!("incoming,{local_addr},{send_back_addr}");
-                }
-                SwarmEvent::OutgoingConnectionError { error, .. } => {
-                    println!("error,{error}");
-                }
-                SwarmEvent::Behaviour(behaviour_event) => match behaviour_event {
-                    BehaviourEvent::Ping(ping::Event { peer, result, .. }) => {
-                        match result {
-                            Ok(rtt) => {
-                                println!("ping,{peer},{} ms", rtt.as_millis());
-                            }
-                            Err(failure) => {
-                                println!("error,{failure}");
-                            }
+    let outcome = checker_core::with_stage_timeout(
+        STAGE,
+        Duration::from_secs(STAGE_TIMEOUT_SECS),
+        async {
+            loop {
+                tokio::select! {
+                    Some(event) = swarm.next() => match event {
+                        SwarmEvent::ConnectionEstablished { peer_id, connection_id, endpoint, .. } => {
+                            println!("connected,{peer_id},{}", endpoint.get_remote_address());
                         }
-                    }
-                    BehaviourEvent::Identify(identify_event) => {
-                        match identify_event {
-                            identify::Event::Received { peer_id, info, .. } => {
-                                println!("identify,{peer_id},{},{}", info.protocol_version, info.agent_version);
-                            }
-                            identify::Event::Error { error, .. } => {
+                        SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                            if let Some(error) = cause {
                                 println!("error,{error}");
+                            } else {
+                                println!("closed,{peer_id}");
                             }
-                            _ => {}
+                            return Ok(())
                         }
-                    }
-                    BehaviourEvent::Gossipsub(gossipsub_event) => {
-                        match gossipsub_event {
-                            gossipsub::Event::Message { message, .. } => {
-                                if let Ok(msg) = UniversalConnectivityMessage::decode(&message.data[..]) {
-                                    println!("msg,{},{},{}",
-                                        msg.from,
-                                        message.topic,
-                                        msg.message);
-                                } else {
-                                    println!("error,{}", message.topic);
+                        SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
+                            println!("incoming,{local_addr},{send_back_addr}");
+                        }
+                        SwarmEvent::OutgoingConnectionError { error, .. } => {
+                            println!("error,{error}");
+                        }
+                        SwarmEvent::Behaviour(behaviour_event) => match behaviour_event {
+                            BehaviourEvent::Ping(ping::Event { peer, result, .. }) => {
+                                match result {
+                                    Ok(rtt) => {
+                                        println!("ping,{peer},{} ms", rtt.as_millis());
+                                    }
+                                    Err(failure) => {
+                                        println!("error,{failure}");
+                                    }
                                 }
                             }
-                            gossipsub::Event::Subscribed { peer_id, topic } => {
-                                println!("subscribe,{peer_id},{topic}");
-                            }
-                            gossipsub::Event::Unsubscribed { peer_id, topic } => {
-                                println!("unsubscribe,{peer_id},{topic}");
+                            BehaviourEvent::Identify(identify_event) => {
+                                match identify_event {
+                                    identify::Event::Received { peer_id, info, .. } => {
+                                        println!("identify,{peer_id},{},{}", info.protocol_version, info.agent_version);
+                                    }
+                                    identify::Event::Error { error, .. } => {
+                                        println!("error,{error}");
+                                    }
+                                    _ => {}
+                                }
                             }
-                            _ => {}
-                        }
-                    }
-                    BehaviourEvent::Kademlia(kad_event) => {
-                        match kad_event {
-                            kad::Event::OutboundQueryProgressed { result, .. } => {
-                                match result {
-                                    kad::QueryResult::Bootstrap(Ok(kad::BootstrapOk {
-                                        num_remaining, ..
-                                    })) => {
-                                        if num_remaining == 0 {
-                                            println!("bootstrap");
+                            BehaviourEvent::Gossipsub(gossipsub_event) => {
+                                match gossipsub_event {
+                                    gossipsub::Event::Message { message, .. } => {
+                                        if let Ok(msg) = UniversalConnectivityMessage::decode(&message.data[..]) {
+                                            println!("msg,{},{},{}",
+                                                msg.from,
+                                                message.topic,
+                                                msg.message);
+                                        } else {
+                                            println!("error,{}", message.topic);
                                         }
                                     }
-                                    kad::QueryResult::Bootstrap(Err(kad::BootstrapError::Timeout { .. })) => {
-                                        println!("error,bootstrap timed out");
+                                    gossipsub::Event::Subscribed { peer_id, topic } => {
+                                        println!("subscribe,{peer_id},{topic}");
                                     }
-                                    kad::QueryResult::GetClosestPeers(Ok(kad::GetClosestPeersOk { peers, .. })) => {
-                                        let mut out = String::from("closestpeers,");
-                                        for (i, peer) in peers.iter().enumerate() {
-                                            if i > 0 {
-                                                out.push(',');
+                                    gossipsub::Event::Unsubscribed { peer_id, topic } => {
+                                        println!("unsubscribe,{peer_id},{topic}");
+                                    }
+                                    _ => {}
+                                }
+                            }
+                            BehaviourEvent::Kademlia(kad_event) => {
+                                match kad_event {
+                                    kad::Event::OutboundQueryProgressed { result, .. } => {
+                                        match result {
+                                            kad::QueryResult::Bootstrap(Ok(kad::BootstrapOk {
+                                                num_remaining, ..
+                                            })) => {
+                                                if num_remaining == 0 {
+                                                    println!("bootstrap");
+                                                    checker_core::emit(STAGE, "bootstrap", None, None, Some(CheckOutcome::Pass));
+                                                }
+                                            }
+                                            kad::QueryResult::Bootstrap(Err(kad::BootstrapError::Timeout { .. })) => {
+                                                println!("error,bootstrap timed out");
+                                                checker_core::emit(STAGE, "bootstrap", None, Some("timed out".to_string()), Some(CheckOutcome::Fail { reason: "bootstrap timed out".to_string() }));
+                                            }
+                                            kad::QueryResult::GetClosestPeers(Ok(kad::GetClosestPeersOk { peers, .. })) => {
+                                                let mut out = String::from("closestpeers,");
+                                                for (i, peer) in peers.iter().enumerate() {
+                                                    if i > 0 {
+                                                        out.push(',');
+                                                    }
+                                                    write!(&mut out, "{}", peer.peer_id)?;
+                                                    for addr in &peer.addrs {
+                                                        write!(&mut out, "-{addr}")?;
+                                                    }
+                                                }
                                             }
-                                            write!(&mut out, "{}", peer.peer_id)?;
-                                            for addr in &peer.addrs {
-                                                write!(&mut out, "-{addr}")?;
+                                            kad::QueryResult::GetClosestPeers(Err(kad::GetClosestPeersError::Timeout { .. })) => {
+                                                println!("error,get closest peers timed out");
                                             }
+                                            _ => {}
                                         }
                                     }
-                                    kad::QueryResult::GetClosestPeers(Err(kad::GetClosestPeersError::Timeout { .. })) => {
-                                        println!("error,get closest peers timed out");
+                                    kad::Event::RoutingUpdated { peer, is_new_peer, addresses, old_peer, .. } => {
+                                        if is_new_peer {
+                                            println!("New peer added to routing table: {peer} with {} addresses", addresses.len());
+                                        }
+                                        if let Some(old) = old_peer {
+                                            println!("Peer {peer} replaced {old} in routing table");
+                                        }
+                                    }
+                                    kad::Event::UnroutablePeer { peer } => {
+                                        println!("Peer {peer} is unroutable");
+                                    }
+                                    kad::Event::RoutablePeer { peer, address } => {
+                                        println!("Peer {peer} is routable at {address}");
                                     }
                                     _ => {}
                                 }
                             }
-                            kad::Event::RoutingUpdated { peer, is_new_peer, addresses, old_peer, .. } => {
-                                if is_new_peer {
-                                    println!("New peer added to routing table: {peer} with {} addresses", addresses.len());
-                                }
-                                if let Some(old) = old_peer {
-                                    println!("Peer {peer} replaced {old} in routing table");
-                                }
-                            }
-                            kad::Event::UnroutablePeer { peer } => {
-                                println!("Peer {peer} is unroutable");
-                            }
-                            kad::Event::RoutablePeer { peer, address } => {
-                                println!("Peer {peer} is routable at {address}");
-                            }
-                            _ => {}
                         }
+                        _ => {}
                     }
                 }
-                _ => {}
             }
-        }
+        },
+    )
+    .await;
+
+    match outcome {
+        Ok(result) => result,
+        Err(_) => Ok(()),
     }
 }
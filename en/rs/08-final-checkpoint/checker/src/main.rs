@@ -1,4 +1,5 @@
 use anyhow::Result;
+use checker_core::CheckOutcome;
 use futures::StreamExt;
 use libp2p::identity;
 use libp2p::{
@@ -70,8 +71,14 @@ struct Behaviour {
     kademlia: kad::Behaviour<kad::store::MemoryStore>,
 }
 
+const STAGE: &str = "final-checkpoint";
+// hard ceiling on the whole stage event loop, so a student node that
+// never emits the expected terminal event can't hang the checker forever
+const STAGE_TIMEOUT_SECS: u64 = 60;
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    checker_core::init(STAGE);
     println!("Starting Universal Connectivity Application...");
 
     let remote_peer = env::var("REMOTE_PEER")?;
@@ -134,113 +141,126 @@ async fn main() -> Result<()> {
     // Send a welcome chat message after connecting
     let mut sent_welcome = false;
 
-    loop {
-        tokio::select! {
-            Some(event) = swarm.next() => match event {
-                SwarmEvent::NewListenAddr { address, .. } => {
-                    println!("Listening on: {address}");
-                }
-                SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
-                    println!("Connected to: {peer_id} via {}", endpoint.get_remote_address());
-
-                    // Send welcome message once when first peer connects
-                    if !sent_welcome {
-                        let welcome_msg = UniversalConnectivityMessage {
-                            message: Some(universal_connectivity_message::Message::Chat(ChatMessage {
-                                message: "Hello from the Universal Connectivity checker!".to_string(),
-                            })),
-                        };
-
-                        let mut buf = Vec::new();
-                        prost::Message::encode(&welcome_msg, &mut buf)?;
-
-                        if let Err(e) = swarm.behaviour_mut().gossipsub.publish(chat_topic.clone(), buf) {
-                            println!("Failed to publish welcome message: {e}");
-                        } else {
-                            println!("Sent welcome chat message to connected peers");
+    let outcome = checker_core::with_stage_timeout(
+        STAGE,
+        Duration::from_secs(STAGE_TIMEOUT_SECS),
+        async {
+            loop {
+                tokio::select! {
+                    Some(event) = swarm.next() => match event {
+                        SwarmEvent::NewListenAddr { address, .. } => {
+                            println!("Listening on: {address}");
                         }
-                        sent_welcome = true;
-                    }
-                }
-                SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
-                    if let Some(err) = cause {
-                        println!("Connection to {peer_id} closed with error: {err}");
-                    } else {
-                        println!("Connection to {peer_id} closed gracefully");
-                    }
-                }
-                SwarmEvent::Behaviour(event) => match event {
-                    BehaviourEvent::Ping(ping_event) => {
-                        match ping_event {
-                            ping::Event { peer, result: Ok(rtt), .. } => {
-                                println!("Received a ping from {peer}, round trip time: {} ms", rtt.as_millis());
-                            }
-                            ping::Event { peer, result: Err(failure), .. } => {
-                                println!("Ping failed to {peer}: {failure:?}");
+                        SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                            println!("Connected to: {peer_id} via {}", endpoint.get_remote_address());
+
+                            // Send welcome message once when first peer connects
+                            if !sent_welcome {
+                                let welcome_msg = UniversalConnectivityMessage {
+                                    message: Some(universal_connectivity_message::Message::Chat(ChatMessage {
+                                        message: "Hello from the Universal Connectivity checker!".to_string(),
+                                    })),
+                                };
+
+                                let mut buf = Vec::new();
+                                prost::Message::encode(&welcome_msg, &mut buf)?;
+
+                                if let Err(e) = swarm.behaviour_mut().gossipsub.publish(chat_topic.clone(), buf) {
+                                    println!("Failed to publish welcome message: {e}");
+                                } else {
+                                    println!("Sent welcome chat message to connected peers");
+                                }
+                                sent_welcome = true;
                             }
                         }
-                    }
-                    BehaviourEvent::Identify(identify_event) => {
-                        match identify_event {
-                            identify::Event::Received { peer_id, info, .. } => {
-                                println!("Identified peer: {} with protocol version: {}", peer_id, info.protocol_version);
-                                println!("Peer agent: {}", info.agent_version);
-                                println!("Peer supports {} protocols", info.protocols.len());
+                        SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                            if let Some(err) = cause {
+                                println!("Connection to {peer_id} closed with error: {err}");
+                            } else {
+                                println!("Connection to {peer_id} closed gracefully");
                             }
-                            identify::Event::Sent { peer_id, .. } => {
-                                println!("Sent identify info to: {peer_id}");
+                        }
+                        SwarmEvent::Behaviour(event) => match event {
+                            BehaviourEvent::Ping(ping_event) => {
+                                match ping_event {
+                                    ping::Event { peer, result: Ok(rtt), .. } => {
+                                        println!("Received a ping from {peer}, round trip time: {} ms", rtt.as_millis());
+                                    }
+                                    ping::Event { peer, result: Err(failure), .. } => {
+                                        println!("Ping failed to {peer}: {failure:?}");
+                                    }
+                                }
                             }
-                            identify::Event::Error { peer_id, error, .. } => {
-                                println!("Identify error with {peer_id}: {error:?}");
+                            BehaviourEvent::Identify(identify_event) => {
+                                match identify_event {
+                                    identify::Event::Received { peer_id, info, .. } => {
+                                        println!("Identified peer: {} with protocol version: {}", peer_id, info.protocol_version);
+                                        println!("Peer agent: {}", info.agent_version);
+                                        println!("Peer supports {} protocols", info.protocols.len());
+                                    }
+                                    identify::Event::Sent { peer_id, .. } => {
+                                        println!("Sent identify info to: {peer_id}");
+                                    }
+                                    identify::Event::Error { peer_id, error, .. } => {
+                                        println!("Identify error with {peer_id}: {error:?}");
+                                    }
+                                    _ => {}
+                                }
                             }
-                            _ => {}
-                        }
-                    }
-                    BehaviourEvent::Gossipsub(gossipsub_event) => match gossipsub_event {
-                        gossipsub::Event::Message {
-                            message,
-                            propagation_source: peer_id,
-                            ..
-                        } => {
-                            // Try to decode as UniversalConnectivityMessage
-                            match UniversalConnectivityMessage::decode(&message.data[..]) {
-                                Ok(uc_msg) => {
-                                    match uc_msg.message {
-                                        Some(universal_connectivity_message::Message::Chat(chat)) => {
-                                            println!("Received chat message from {peer_id}: {}", chat.message);
+                            BehaviourEvent::Gossipsub(gossipsub_event) => match gossipsub_event {
+                                gossipsub::Event::Message {
+                                    message,
+                                    propagation_source: peer_id,
+                                    ..
+                                } => {
+                                    // Try to decode as UniversalConnectivityMessage
+                                    match UniversalConnectivityMessage::decode(&message.data[..]) {
+                                        Ok(uc_msg) => {
+                                            match uc_msg.message {
+                                                Some(universal_connectivity_message::Message::Chat(chat)) => {
+                                                    println!("Received chat message from {peer_id}: {}", chat.message);
+                                                    checker_core::emit(STAGE, "chat", Some(peer_id.to_string()), Some(chat.message.clone()), Some(CheckOutcome::Pass));
+                                                }
+                                                Some(universal_connectivity_message::Message::File(file)) => {
+                                                    println!("Received file message from {peer_id}: {} ({} bytes)", file.name, file.size);
+                                                }
+                                                _ => {
+                                                    println!("Received other gossipsub message from {peer_id}");
+                                                }
+                                            }
                                         }
-                                        Some(universal_connectivity_message::Message::File(file)) => {
-                                            println!("Received file message from {peer_id}: {} ({} bytes)", file.name, file.size);
-                                        }
-                                        _ => {
-                                            println!("Received other gossipsub message from {peer_id}");
+                                        Err(_) => {
+                                            // Fallback to raw message display
+                                            if let Ok(text) = String::from_utf8(message.data.clone()) {
+                                                println!("Received raw gossipsub message from {peer_id}: {text}");
+                                            } else {
+                                                println!("Received binary gossipsub message from {peer_id}");
+                                            }
                                         }
                                     }
                                 }
-                                Err(_) => {
-                                    // Fallback to raw message display
-                                    if let Ok(text) = String::from_utf8(message.data.clone()) {
-                                        println!("Received raw gossipsub message from {peer_id}: {text}");
-                                    } else {
-                                        println!("Received binary gossipsub message from {peer_id}");
-                                    }
+                                gossipsub::Event::Subscribed { peer_id, topic } => {
+                                    println!("Peer {peer_id} subscribed to topic: {topic}");
                                 }
+                                gossipsub::Event::Unsubscribed { peer_id, topic } => {
+                                    println!("Peer {peer_id} unsubscribed from topic: {topic}");
+                                }
+                                _ => {}
+                            }
+                            BehaviourEvent::Kademlia(kad_event) => {
+                                println!("Kademlia event: {kad_event:?}");
                             }
-                        }
-                        gossipsub::Event::Subscribed { peer_id, topic } => {
-                            println!("Peer {peer_id} subscribed to topic: {topic}");
-                        }
-                        gossipsub::Event::Unsubscribed { peer_id, topic } => {
-                            println!("Peer {peer_id} unsubscribed from topic: {topic}");
                         }
                         _ => {}
                     }
-                    BehaviourEvent::Kademlia(kad_event) => {
-                        println!("Kademlia event: {kad_event:?}");
-                    }
                 }
-                _ => {}
             }
-        }
+        },
+    )
+    .await;
+
+    match outcome {
+        Ok(result) => result,
+        Err(_) => Ok(()),
     }
 }
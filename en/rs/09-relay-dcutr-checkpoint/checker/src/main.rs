@@ -0,0 +1,241 @@
+use anyhow::Result;
+use checker_core::CheckOutcome;
+use futures::StreamExt;
+use libp2p::{
+    dcutr, identify, identity,
+    multiaddr::Protocol,
+    noise, ping, relay, yamux,
+    swarm::{NetworkBehaviour, SwarmEvent},
+    Multiaddr, PeerId, SwarmBuilder,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    str::FromStr,
+    time::Duration,
+};
+
+const IDENTIFY_PROTOCOL_VERSION: &str = "/ipfs/id/1.0.0";
+const AGENT_VERSION: &str = "universal-connectivity/0.1.0";
+const STAGE: &str = "relay-dcutr-checkpoint";
+// hard ceiling on the whole stage event loop, so a student node that
+// never emits the expected terminal event can't hang the checker forever
+const STAGE_TIMEOUT_SECS: u64 = 60;
+
+// Define a custom network behaviour that includes ping, identify, relay
+// server, relay client, and DCUtR functionality. We are both the relay the
+// student reserves a circuit through (`relay`) and, once that reservation
+// lands, the peer that dials them through it (`relay_client`) to drive the
+// DCUtR hole-punch from our side.
+#[derive(NetworkBehaviour)]
+struct Behaviour {
+    ping: ping::Behaviour,
+    identify: identify::Behaviour,
+    relay: relay::Behaviour,
+    relay_client: relay::client::Behaviour,
+    dcutr: dcutr::Behaviour,
+}
+
+fn is_relayed(addr: &Multiaddr) -> bool {
+    addr.iter().any(|p| p == Protocol::P2pCircuit)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    checker_core::init(STAGE);
+
+    // the addresses we listen on; these double as our relay address, since
+    // this process is itself the relay server the student reserves against
+    let remote_peers = env::var("REMOTE_PEERS")?;
+    let remote_addrs: Vec<Multiaddr> = remote_peers
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(Multiaddr::from_str)
+        .collect::<Result<_, _>>()?;
+
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_peer_id = local_key.public().to_peer_id();
+
+    let mut swarm = SwarmBuilder::with_existing_identity(local_key)
+        .with_tokio()
+        .with_tcp(
+            Default::default(),
+            noise::Config::new,
+            yamux::Config::default,
+        )?
+        .with_quic()
+        .with_relay_client(noise::Config::new, yamux::Config::default)?
+        .with_behaviour(|key, relay_client| Behaviour {
+            ping: ping::Behaviour::new(
+                ping::Config::new()
+                    .with_interval(Duration::from_secs(1))
+                    .with_timeout(Duration::from_secs(5)),
+            ),
+            identify: identify::Behaviour::new(
+                identify::Config::new(IDENTIFY_PROTOCOL_VERSION.to_string(), key.public())
+                    .with_agent_version(AGENT_VERSION.to_string()),
+            ),
+            relay: relay::Behaviour::new(key.public().to_peer_id(), relay::Config::default()),
+            relay_client,
+            dcutr: dcutr::Behaviour::new(key.public().to_peer_id()),
+        })?
+        .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+        .build();
+
+    // listen on all addresses
+    for addr in remote_addrs.into_iter() {
+        swarm.listen_on(addr)?;
+    }
+
+    // the first external address we see ourselves listening on, used to
+    // build `<our_addr>/p2p/<us>/p2p-circuit/p2p/<student>` once we know the
+    // student's peer id from their accepted reservation
+    let mut external_addr: Option<Multiaddr> = None;
+    // students we've already dialed through our own relay circuit, so a
+    // renewed reservation doesn't trigger a second redundant dial
+    let mut dialed_via_circuit: HashSet<PeerId> = HashSet::new();
+    // the most recent non-relay address each peer connected on, reported as
+    // the direct address once DCUtR says the upgrade succeeded
+    let mut direct_addrs: HashMap<PeerId, Multiaddr> = HashMap::new();
+    // peers whose DCUtR result reported success before their direct
+    // ConnectionEstablished event landed, so we can emit the Pass once the
+    // address shows up instead of dropping it on the floor
+    let mut pending_holepunches: HashSet<PeerId> = HashSet::new();
+
+    let outcome = checker_core::with_stage_timeout(
+        STAGE,
+        Duration::from_secs(STAGE_TIMEOUT_SECS),
+        async {
+            loop {
+                tokio::select! {
+                    Some(event) = swarm.next() => match event {
+                        SwarmEvent::NewListenAddr { address, .. } => {
+                            println!("listening,{address}");
+                            external_addr.get_or_insert(address);
+                        }
+                        SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                            let addr = endpoint.get_remote_address();
+                            if is_relayed(addr) {
+                                println!("relayed,{peer_id},{addr}");
+                            } else {
+                                println!("connected,{peer_id},{addr}");
+                                direct_addrs.insert(peer_id, addr.clone());
+                                if pending_holepunches.remove(&peer_id) {
+                                    println!("holepunch,{peer_id},{addr}");
+                                    checker_core::emit(STAGE, "holepunch", Some(peer_id.to_string()), Some(addr.to_string()), Some(CheckOutcome::Pass));
+                                    return Ok(());
+                                }
+                            }
+                        }
+                        SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                            if let Some(error) = cause {
+                                println!("error,{error}");
+                            } else {
+                                println!("closed,{peer_id}");
+                            }
+                        }
+                        SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
+                            println!("incoming,{local_addr},{send_back_addr}");
+                        }
+                        SwarmEvent::OutgoingConnectionError { error, .. } => {
+                            println!("error,{error}");
+                        }
+                        SwarmEvent::Behaviour(behaviour_event) => match behaviour_event {
+                            BehaviourEvent::Ping(ping::Event { peer, result, .. }) => {
+                                match result {
+                                    Ok(rtt) => {
+                                        println!("ping,{peer},{} ms", rtt.as_millis());
+                                    }
+                                    Err(failure) => {
+                                        println!("error,{failure}");
+                                    }
+                                }
+                            }
+                            BehaviourEvent::Identify(identify_event) => {
+                                match identify_event {
+                                    identify::Event::Received { peer_id, info, .. } => {
+                                        println!("identify,{peer_id},{},{}", info.protocol_version, info.agent_version);
+                                    }
+                                    identify::Event::Error { error, .. } => {
+                                        println!("error,{error}");
+                                    }
+                                    _ => {}
+                                }
+                            }
+                            // we're acting as the relay server here: this fires once the
+                            // student's reservation request lands, making them reachable
+                            // at `<our_addr>/p2p/<us>/p2p-circuit/p2p/<student>`
+                            BehaviourEvent::Relay(relay::Event::ReservationReqAccepted { src_peer_id, .. }) => {
+                                println!("reservation,{src_peer_id}");
+                                checker_core::emit(STAGE, "reservation", Some(src_peer_id.to_string()), None, Some(CheckOutcome::Pass));
+
+                                // broker the relayed connection ourselves: dial the student
+                                // back through the circuit we just accepted their reservation
+                                // on, which is what gives DCUtR a relayed connection to
+                                // upgrade to a direct one
+                                if dialed_via_circuit.insert(src_peer_id) {
+                                    if let Some(addr) = &external_addr {
+                                        let circuit_addr = addr
+                                            .clone()
+                                            .with(Protocol::P2p(local_peer_id))
+                                            .with(Protocol::P2pCircuit)
+                                            .with(Protocol::P2p(src_peer_id));
+                                        if let Err(error) = swarm.dial(circuit_addr) {
+                                            println!("error,failed to dial {src_peer_id} via relay circuit: {error}");
+                                        }
+                                    } else {
+                                        println!("error,no known external address to relay {src_peer_id} through yet");
+                                    }
+                                }
+                            }
+                            BehaviourEvent::Relay(relay::Event::ReservationReqDenied { src_peer_id }) => {
+                                println!("error,reservation from {src_peer_id} denied");
+                            }
+                            BehaviourEvent::Relay(_) => {}
+                            BehaviourEvent::RelayClient(relay_event) => {
+                                match relay_event {
+                                    relay::client::Event::OutboundCircuitEstablished { relay_peer_id, .. } => {
+                                        println!("relayed,{relay_peer_id}");
+                                    }
+                                    _ => {}
+                                }
+                            }
+                            BehaviourEvent::Dcutr(dcutr::Event { remote_peer_id, result }) => {
+                                match result {
+                                    Ok(_) => {
+                                        if let Some(addr) = direct_addrs.get(&remote_peer_id) {
+                                            println!("holepunch,{remote_peer_id},{addr}");
+                                            checker_core::emit(STAGE, "holepunch", Some(remote_peer_id.to_string()), Some(addr.to_string()), Some(CheckOutcome::Pass));
+                                            return Ok(());
+                                        } else {
+                                            // DCUtR reports success before the direct
+                                            // ConnectionEstablished event lands; defer
+                                            // the Pass emit until that event arrives
+                                            // and fills in direct_addrs above
+                                            println!("holepunch,{remote_peer_id},pending");
+                                            pending_holepunches.insert(remote_peer_id);
+                                        }
+                                    }
+                                    Err(error) => {
+                                        // direct upgrade failed; we're stuck on the relayed path
+                                        println!("error,holepunch with {remote_peer_id} failed: {error}");
+                                        checker_core::emit(STAGE, "holepunch", Some(remote_peer_id.to_string()), Some(error.to_string()), Some(CheckOutcome::Fail { reason: error.to_string() }));
+                                        return Ok(());
+                                    }
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        },
+    )
+    .await;
+
+    match outcome {
+        Ok(result) => result,
+        Err(_) => Ok(()),
+    }
+}
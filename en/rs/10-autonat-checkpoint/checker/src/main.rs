@@ -0,0 +1,168 @@
+use anyhow::Result;
+use checker_core::CheckOutcome;
+use futures::StreamExt;
+use libp2p::{
+    autonat, identify, identity, ping,
+    swarm::{NetworkBehaviour, SwarmEvent},
+    Multiaddr, SwarmBuilder,
+};
+use std::{env, str::FromStr, time::Duration};
+
+const IDENTIFY_PROTOCOL_VERSION: &str = "/ipfs/id/1.0.0";
+const AGENT_VERSION: &str = "universal-connectivity/0.1.0";
+const NAT_PROBE_TIMEOUT: u64 = 30;
+const STAGE: &str = "autonat-checkpoint";
+
+// Define a custom network behaviour that includes ping, identify, and AutoNAT
+// reachability detection
+#[derive(NetworkBehaviour)]
+struct Behaviour {
+    ping: ping::Behaviour,
+    identify: identify::Behaviour,
+    autonat: autonat::Behaviour,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    checker_core::init(STAGE);
+
+    let remote_peers = env::var("REMOTE_PEERS")?;
+    let remote_addrs: Vec<Multiaddr> = remote_peers
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(Multiaddr::from_str)
+        .collect::<Result<_, _>>()?;
+
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_peer_id = local_key.public().to_peer_id();
+
+    let mut swarm = SwarmBuilder::with_existing_identity(local_key)
+        .with_tokio()
+        .with_tcp(
+            Default::default(),
+            libp2p::noise::Config::new,
+            libp2p::yamux::Config::default,
+        )?
+        .with_quic()
+        .with_behaviour(|key| Behaviour {
+            ping: ping::Behaviour::new(
+                ping::Config::new()
+                    .with_interval(Duration::from_secs(1))
+                    .with_timeout(Duration::from_secs(5)),
+            ),
+            identify: identify::Behaviour::new(
+                identify::Config::new(IDENTIFY_PROTOCOL_VERSION.to_string(), key.public())
+                    .with_agent_version(AGENT_VERSION.to_string()),
+            ),
+            autonat: autonat::Behaviour::new(local_peer_id, autonat::Config::default()),
+        })?
+        .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+        .build();
+
+    // listen on all addresses
+    for addr in remote_addrs.into_iter() {
+        swarm.listen_on(addr)?;
+    }
+
+    // fail the stage if the student's NAT status is still Unknown once this
+    // many seconds have elapsed since startup
+    let outcome = checker_core::with_stage_timeout(
+        STAGE,
+        Duration::from_secs(NAT_PROBE_TIMEOUT),
+        async {
+            loop {
+                tokio::select! {
+                    Some(event) = swarm.next() => match event {
+                        SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                            println!("connected,{peer_id},{}", endpoint.get_remote_address());
+                        }
+                        SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                            if let Some(error) = cause {
+                                println!("error,{error}");
+                            } else {
+                                println!("closed,{peer_id}");
+                            }
+                        }
+                        SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
+                            println!("incoming,{local_addr},{send_back_addr}");
+                        }
+                        SwarmEvent::OutgoingConnectionError { error, .. } => {
+                            println!("error,{error}");
+                        }
+                        SwarmEvent::Behaviour(behaviour_event) => match behaviour_event {
+                            BehaviourEvent::Ping(ping::Event { peer, result, .. }) => {
+                                match result {
+                                    Ok(rtt) => {
+                                        println!("ping,{peer},{} ms", rtt.as_millis());
+                                    }
+                                    Err(failure) => {
+                                        println!("error,{failure}");
+                                    }
+                                }
+                            }
+                            BehaviourEvent::Identify(identify_event) => {
+                                match identify_event {
+                                    identify::Event::Received { peer_id, info, .. } => {
+                                        println!("identify,{peer_id},{},{}", info.protocol_version, info.agent_version);
+                                    }
+                                    identify::Event::Error { error, .. } => {
+                                        println!("error,{error}");
+                                    }
+                                    _ => {}
+                                }
+                            }
+                            // the student's autonat client sends us (acting as its autonat
+                            // server) a dial-back request; we attempt the reverse dial and
+                            // these events report how that attempt went, which is exactly
+                            // what drives the student's own NatStatus transition
+                            BehaviourEvent::Autonat(autonat::Event::InboundProbe(probe_event)) => {
+                                match probe_event {
+                                    autonat::InboundProbeEvent::Request { peer, addresses, .. } => {
+                                        println!("nat,dialback-requested,{peer},{}", addresses.len());
+                                    }
+                                    autonat::InboundProbeEvent::Response { peer, address, .. } => {
+                                        // the reverse dial to one of the student's advertised
+                                        // addresses succeeded: the student will see itself as
+                                        // publicly reachable
+                                        println!("nat,public,{address}");
+                                        checker_core::emit(STAGE, "nat", Some(peer.to_string()), Some(format!("public {address}")), Some(CheckOutcome::Pass));
+                                        return Ok(())
+                                    }
+                                    autonat::InboundProbeEvent::Error { peer, error, .. } => {
+                                        // the reverse dial failed: the student will see itself
+                                        // as private, which is still a correctly completed probe
+                                        println!("nat,private,-");
+                                        checker_core::emit(STAGE, "nat", Some(peer.to_string()), Some(error.to_string()), Some(CheckOutcome::Pass));
+                                        return Ok(())
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        },
+    )
+    .await;
+
+    match outcome {
+        Ok(result) => result,
+        Err(_) => {
+            // the shared helper already emitted a generic timeout record; add
+            // the stage-specific detail so the human-readable output still
+            // says what we were waiting on
+            println!("error,nat status still unknown after {NAT_PROBE_TIMEOUT}s");
+            checker_core::emit(
+                STAGE,
+                "nat",
+                None,
+                Some("status still unknown".to_string()),
+                Some(CheckOutcome::Timeout),
+            );
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use anyhow::{Context, Result};
+use checker_core::CheckOutcome;
+use futures::StreamExt;
+use libp2p::{
+    core::{muxing::StreamMuxerBox, transport::Boxed, upgrade},
+    identity, noise, ping,
+    pnet::{PnetConfig, PreSharedKey},
+    swarm::{NetworkBehaviour, SwarmEvent},
+    yamux, Multiaddr, PeerId, SwarmBuilder, Transport,
+};
+use std::{env, str::FromStr, time::Duration};
+
+const STAGE: &str = "pnet-checkpoint";
+// hard ceiling on each sub-check's dial, so a student node that never
+// completes (or never rejects) the pnet handshake can't hang the checker
+const DIAL_TIMEOUT_SECS: u64 = 30;
+
+// Define a custom network behaviour that includes ping functionality; the
+// private-network gating happens below it, at the transport layer
+#[derive(NetworkBehaviour)]
+struct Behaviour {
+    ping: ping::Behaviour,
+}
+
+// the raw 32-byte pre-shared key, hex-decoded from the PSK env var
+fn read_psk_bytes() -> Result<[u8; 32]> {
+    let hex_key = env::var("PSK").context("PSK env var not set")?;
+    let bytes = hex::decode(hex_key.trim()).context("PSK is not valid hex")?;
+    bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("PSK must decode to exactly 32 bytes"))
+}
+
+// wraps the raw TCP connection with the pnet handshake ahead of noise/yamux,
+// so peers that don't share our PSK never get that far
+fn build_transport(
+    local_key: identity::Keypair,
+    psk: PreSharedKey,
+) -> Result<Boxed<(PeerId, StreamMuxerBox)>> {
+    let noise_config = noise::Config::new(&local_key)?;
+    let yamux_config = yamux::Config::default();
+
+    Ok(libp2p::tcp::tokio::Transport::new(libp2p::tcp::Config::default())
+        .and_then(move |socket, _| PnetConfig::new(psk).handshake(socket))
+        .upgrade(upgrade::Version::V1)
+        .authenticate(noise_config)
+        .multiplex(yamux_config)
+        .boxed())
+}
+
+// a PSK-mismatch handshake failure surfaces as a plain io error from the
+// pnet layer, distinguishable from an ordinary dial error (unreachable
+// address, connection refused, ...) by its message
+fn is_psk_mismatch(error: &libp2p::swarm::DialError) -> bool {
+    error.to_string().to_lowercase().contains("pnet")
+}
+
+// flips a byte of the real PSK so the second sub-check dials with a key the
+// student's swarm does not share
+fn wrong_psk(psk: [u8; 32]) -> [u8; 32] {
+    let mut bytes = psk;
+    bytes[0] ^= 0xff;
+    bytes
+}
+
+// dials the student once with the given PSK and reports whether the
+// handshake completed (`true`) or was rejected as a PSK mismatch (`false`);
+// any other outcome (unexpected error, no ping, connection dropped) is an
+// error so the caller can fail the sub-check
+async fn dial_once(local_key: identity::Keypair, remote_addrs: &[Multiaddr], psk: [u8; 32]) -> Result<bool> {
+    let transport = build_transport(local_key.clone(), PreSharedKey::new(psk))?;
+
+    let mut swarm = SwarmBuilder::with_existing_identity(local_key)
+        .with_tokio()
+        .with_other_transport(|_| transport)?
+        .with_behaviour(|_| Behaviour {
+            ping: ping::Behaviour::new(
+                ping::Config::new()
+                    .with_interval(Duration::from_secs(1))
+                    .with_timeout(Duration::from_secs(5)),
+            ),
+        })?
+        .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+        .build();
+
+    for addr in remote_addrs.iter().cloned() {
+        swarm.dial(addr)?;
+    }
+
+    loop {
+        match swarm.select_next_some().await {
+            SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                println!("connected,{peer_id},{}", endpoint.get_remote_address());
+            }
+            SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                if let Some(error) = cause {
+                    anyhow::bail!("{peer_id} closed unexpectedly: {error}");
+                }
+                anyhow::bail!("{peer_id} closed before a ping round-trip");
+            }
+            SwarmEvent::OutgoingConnectionError { error, .. } => {
+                if is_psk_mismatch(&error) {
+                    println!("rejected,{error}");
+                    return Ok(false);
+                }
+                anyhow::bail!("dial failed for a reason other than a PSK mismatch: {error}");
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Ping(ping::Event { peer, result, .. })) => match result {
+                Ok(rtt) => {
+                    println!("ping,{peer},{} ms", rtt.as_millis());
+                    return Ok(true);
+                }
+                Err(failure) => println!("error,{failure}"),
+            },
+            _ => {}
+        }
+    }
+}
+
+// runs `dial_once` under the shared per-stage deadline, so a student node
+// that never completes (or never rejects) the handshake can't hang the
+// checker forever
+async fn dial_with_timeout(
+    local_key: identity::Keypair,
+    remote_addrs: &[Multiaddr],
+    psk: [u8; 32],
+) -> Result<bool> {
+    match checker_core::with_stage_timeout(
+        STAGE,
+        Duration::from_secs(DIAL_TIMEOUT_SECS),
+        dial_once(local_key, remote_addrs, psk),
+    )
+    .await
+    {
+        Ok(result) => result,
+        Err(_) => Err(anyhow::anyhow!(
+            "dial did not resolve within {DIAL_TIMEOUT_SECS}s"
+        )),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    checker_core::init(STAGE);
+
+    let remote_peers = env::var("REMOTE_PEERS")?;
+    let remote_addrs: Vec<Multiaddr> = remote_peers
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(Multiaddr::from_str)
+        .collect::<Result<_, _>>()?;
+
+    let psk = read_psk_bytes()?;
+
+    // sub-check 1: dial with the matching PSK and expect a completed handshake
+    match dial_with_timeout(identity::Keypair::generate_ed25519(), &remote_addrs, psk).await {
+        Ok(true) => {
+            checker_core::emit(STAGE, "matching-psk", None, None, Some(CheckOutcome::Pass));
+        }
+        Ok(false) => {
+            let reason = "dial with the matching PSK was rejected".to_string();
+            checker_core::emit(STAGE, "matching-psk", None, Some(reason.clone()), Some(CheckOutcome::Fail { reason: reason.clone() }));
+            return Err(anyhow::anyhow!(reason));
+        }
+        Err(error) => {
+            checker_core::emit(STAGE, "matching-psk", None, Some(error.to_string()), Some(CheckOutcome::Fail { reason: error.to_string() }));
+            return Err(error);
+        }
+    }
+
+    // sub-check 2: reconnect with a deliberately wrong PSK and expect the
+    // student's pnet layer to reject us
+    match dial_with_timeout(identity::Keypair::generate_ed25519(), &remote_addrs, wrong_psk(psk)).await {
+        Ok(false) => {
+            checker_core::emit(STAGE, "wrong-psk", None, None, Some(CheckOutcome::Pass));
+            Ok(())
+        }
+        Ok(true) => {
+            let reason = "dial with a deliberately wrong PSK was accepted".to_string();
+            checker_core::emit(STAGE, "wrong-psk", None, Some(reason.clone()), Some(CheckOutcome::Fail { reason: reason.clone() }));
+            Err(anyhow::anyhow!(reason))
+        }
+        Err(error) => {
+            checker_core::emit(STAGE, "wrong-psk", None, Some(error.to_string()), Some(CheckOutcome::Fail { reason: error.to_string() }));
+            Err(error)
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use anyhow::Result;
+use checker_core::CheckOutcome;
+use futures::StreamExt;
+use libp2p::{
+    identify, identity, rendezvous,
+    swarm::{NetworkBehaviour, SwarmEvent},
+    Multiaddr, PeerId, SwarmBuilder,
+};
+use std::{env, str::FromStr, time::Duration};
+
+const IDENTIFY_PROTOCOL_VERSION: &str = "/ipfs/id/1.0.0";
+const AGENT_VERSION: &str = "universal-connectivity/0.1.0";
+const STAGE: &str = "rendezvous-checkpoint";
+// hard ceiling on the whole stage event loop, so a student node that
+// never emits the expected terminal event can't hang the checker forever
+const STAGE_TIMEOUT_SECS: u64 = 60;
+
+// Define a custom network behaviour for the rendezvous point: identify plus
+// the rendezvous server itself
+#[derive(NetworkBehaviour)]
+struct Behaviour {
+    identify: identify::Behaviour,
+    rendezvous: rendezvous::server::Behaviour,
+}
+
+// a second, independent identity that plays the role of a discovering peer:
+// it dials the same rendezvous point the student registers against, issues
+// its own `discover`, and confirms it can parse the returned registration
+// and dial the address it carries, which is the half of the round trip the
+// server side alone can never observe
+#[derive(NetworkBehaviour)]
+struct DiscovererBehaviour {
+    rendezvous: rendezvous::client::Behaviour,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    checker_core::init(STAGE);
+
+    let remote_peers = env::var("REMOTE_PEERS")?;
+    let remote_addrs: Vec<Multiaddr> = remote_peers
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(Multiaddr::from_str)
+        .collect::<Result<_, _>>()?;
+
+    // the namespace we expect the student to register under; a registration
+    // under any other namespace is a failure
+    let namespace = rendezvous::Namespace::from_static(
+        Box::leak(env::var("NAMESPACE").unwrap_or_else(|_| "universal-connectivity".to_string()).into_boxed_str()),
+    );
+
+    let local_key = identity::Keypair::generate_ed25519();
+
+    let mut swarm = SwarmBuilder::with_existing_identity(local_key)
+        .with_tokio()
+        .with_tcp(
+            Default::default(),
+            libp2p::noise::Config::new,
+            libp2p::yamux::Config::default,
+        )?
+        .with_quic()
+        .with_behaviour(|key| Behaviour {
+            identify: identify::Behaviour::new(
+                identify::Config::new(IDENTIFY_PROTOCOL_VERSION.to_string(), key.public())
+                    .with_agent_version(AGENT_VERSION.to_string()),
+            ),
+            rendezvous: rendezvous::server::Behaviour::new(rendezvous::server::Config::default()),
+        })?
+        .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+        .build();
+    let local_peer_id = *swarm.local_peer_id();
+
+    // listen on all addresses
+    for addr in remote_addrs.iter() {
+        swarm.listen_on(addr.clone())?;
+    }
+
+    // a second identity that plays the part of a peer discovering the
+    // student through us: it dials in just like the student does, asks us
+    // to discover the namespace, and then has to actually parse and dial
+    // whatever registration comes back
+    let discoverer_key = identity::Keypair::generate_ed25519();
+    let mut discoverer_swarm = SwarmBuilder::with_existing_identity(discoverer_key)
+        .with_tokio()
+        .with_tcp(
+            Default::default(),
+            libp2p::noise::Config::new,
+            libp2p::yamux::Config::default,
+        )?
+        .with_quic()
+        .with_behaviour(|_| DiscovererBehaviour {
+            rendezvous: rendezvous::client::Behaviour::new(),
+        })?
+        .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+        .build();
+    for addr in remote_addrs.iter() {
+        discoverer_swarm.dial(addr.clone())?;
+    }
+
+    // set once the student registers under the expected namespace; this is
+    // the peer id we expect the discoverer to be handed back and to dial
+    let mut registered_peer: Option<PeerId> = None;
+    let mut discoverer_connected_to_us = false;
+    let mut discover_requested = false;
+    // set once the discoverer's own dial of the returned registration
+    // either connects or fails, so we know the round trip actually happened
+    let mut discover_round_trip: Option<bool> = None;
+    let mut expected_dial_peer: Option<PeerId> = None;
+
+    let outcome = checker_core::with_stage_timeout(
+        STAGE,
+        Duration::from_secs(STAGE_TIMEOUT_SECS),
+        async {
+            loop {
+                tokio::select! {
+                    Some(event) = swarm.next() => match event {
+                        SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                            println!("connected,{peer_id},{}", endpoint.get_remote_address());
+                        }
+                        SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                            if let Some(error) = cause {
+                                println!("error,{error}");
+                            } else {
+                                println!("closed,{peer_id}");
+                            }
+                        }
+                        SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
+                            println!("incoming,{local_addr},{send_back_addr}");
+                        }
+                        SwarmEvent::OutgoingConnectionError { error, .. } => {
+                            println!("error,{error}");
+                        }
+                        SwarmEvent::Behaviour(behaviour_event) => match behaviour_event {
+                            BehaviourEvent::Identify(identify_event) => {
+                                match identify_event {
+                                    identify::Event::Received { peer_id, info, .. } => {
+                                        println!("identify,{peer_id},{},{}", info.protocol_version, info.agent_version);
+                                    }
+                                    identify::Event::Error { error, .. } => {
+                                        println!("error,{error}");
+                                    }
+                                    _ => {}
+                                }
+                            }
+                            BehaviourEvent::Rendezvous(rendezvous_event) => {
+                                match rendezvous_event {
+                                    rendezvous::server::Event::PeerRegistered { peer, registration } => {
+                                        if registration.namespace == namespace {
+                                            println!("registered,{},{peer}", registration.namespace);
+                                            checker_core::emit(STAGE, "registered", Some(peer.to_string()), Some(registration.namespace.to_string()), Some(CheckOutcome::Pass));
+                                            registered_peer = Some(peer);
+                                            if discoverer_connected_to_us && !discover_requested {
+                                                discover_requested = true;
+                                                discoverer_swarm.behaviour_mut().rendezvous.discover(Some(namespace.clone()), None, None, local_peer_id);
+                                            }
+                                        } else {
+                                            println!("error,{peer} registered under unexpected namespace {}", registration.namespace);
+                                            checker_core::emit(STAGE, "registered", Some(peer.to_string()), Some(format!("unexpected namespace {}", registration.namespace)), Some(CheckOutcome::Fail { reason: "wrong namespace".to_string() }));
+                                        }
+                                    }
+                                    rendezvous::server::Event::PeerNotRegistered { peer, namespace: attempted, error } => {
+                                        println!("error,{peer} failed to register under {attempted}: {error:?}");
+                                    }
+                                    rendezvous::server::Event::PeerUnregistered { peer, namespace: left } => {
+                                        println!("unregistered,{left},{peer}");
+                                    }
+                                    rendezvous::server::Event::RegistrationExpired(registration) => {
+                                        println!("error,registration for {} expired before discovery", registration.namespace);
+                                    }
+                                    rendezvous::server::Event::DiscoverServed { enquirer, registrations } => {
+                                        println!("discovered,{namespace},{}", registrations.len());
+                                        checker_core::emit(STAGE, "discovered", Some(enquirer.to_string()), Some(registrations.len().to_string()), Some(CheckOutcome::Pass));
+                                    }
+                                    rendezvous::server::Event::DiscoverNotServed { enquirer, error } => {
+                                        println!("error,discover from {enquirer} failed: {error:?}");
+                                    }
+                                }
+                            }
+                        }
+                        _ => {}
+                    },
+                    Some(event) = discoverer_swarm.next() => match event {
+                        SwarmEvent::ConnectionEstablished { peer_id, .. } if peer_id == local_peer_id => {
+                            discoverer_connected_to_us = true;
+                            if registered_peer.is_some() && !discover_requested {
+                                discover_requested = true;
+                                discoverer_swarm.behaviour_mut().rendezvous.discover(Some(namespace.clone()), None, None, local_peer_id);
+                            }
+                        }
+                        SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                            println!("discoverer,connected,{peer_id},{}", endpoint.get_remote_address());
+                            if expected_dial_peer == Some(peer_id) && discover_round_trip.is_none() {
+                                discover_round_trip = Some(true);
+                                checker_core::emit(STAGE, "discover-dial", Some(peer_id.to_string()), None, Some(CheckOutcome::Pass));
+                                return Ok(());
+                            }
+                        }
+                        SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
+                            println!("discoverer,error,{error}");
+                            if expected_dial_peer.is_some() && expected_dial_peer == peer_id && discover_round_trip.is_none() {
+                                discover_round_trip = Some(false);
+                                checker_core::emit(STAGE, "discover-dial", peer_id.map(|p| p.to_string()), Some(error.to_string()), Some(CheckOutcome::Fail { reason: error.to_string() }));
+                                return Ok(());
+                            }
+                        }
+                        SwarmEvent::Behaviour(DiscovererBehaviourEvent::Rendezvous(rendezvous_event)) => {
+                            match rendezvous_event {
+                                rendezvous::client::Event::Discovered { registrations, .. } => {
+                                    for registration in registrations {
+                                        let peer = registration.record.peer_id();
+                                        if registered_peer == Some(peer) {
+                                            expected_dial_peer = Some(peer);
+                                            for addr in registration.record.addresses() {
+                                                println!("discoverer,discovered,{peer},{addr}");
+                                                let _ = discoverer_swarm.dial(addr.clone());
+                                            }
+                                        }
+                                    }
+                                }
+                                rendezvous::client::Event::DiscoverFailed { rendezvous_node, error, .. } => {
+                                    println!("error,discoverer discover from {rendezvous_node} failed: {error:?}");
+                                }
+                                _ => {}
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        },
+    )
+    .await;
+
+    match outcome {
+        Ok(result) => result,
+        Err(_) => Ok(()),
+    }
+}
@@ -0,0 +1,231 @@
+use anyhow::Result;
+use checker_core::CheckOutcome;
+use futures::StreamExt;
+use libp2p::{
+    bandwidth::BandwidthSinks,
+    gossipsub, identity, noise, ping,
+    swarm::{NetworkBehaviour, SwarmEvent},
+    tcp, yamux, Multiaddr, SwarmBuilder,
+};
+use prost::Message;
+use std::{env, str::FromStr, sync::Arc, time::Duration};
+
+const STAGE: &str = "connection-limits-checkpoint";
+// hard ceiling on the whole stage event loop, so a student node that
+// never emits the expected terminal event can't hang the checker forever
+const STAGE_TIMEOUT_SECS: u64 = 60;
+const TICK_INTERVAL: Duration = Duration::from_secs(5);
+const CHAT_TOPIC: &str = "universal-connectivity";
+// a payload of known size, published once a connection lands, so the
+// bandwidth counters have something deliberate to measure instead of
+// whatever ping/identify traffic happens to cross the wire
+const BANDWIDTH_PAYLOAD_LEN: usize = 4096;
+// how many ticks to give the student after the payload is published before
+// failing the bandwidth check outright
+const BANDWIDTH_CHECK_GRACE_TICKS: u32 = 3;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UniversalConnectivityMessage {
+    #[prost(string, tag = "1")]
+    pub from: String,
+    #[prost(string, tag = "2")]
+    pub message: String,
+    #[prost(int64, tag = "3")]
+    pub timestamp: i64,
+}
+
+// Define a custom network behaviour that includes ping and gossipsub;
+// bandwidth accounting lives below it, at the transport layer
+#[derive(NetworkBehaviour)]
+struct Behaviour {
+    ping: ping::Behaviour,
+    gossipsub: gossipsub::Behaviour,
+}
+
+fn env_u32(name: &str, default: u32) -> u32 {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    checker_core::init(STAGE);
+
+    let remote_peers = env::var("REMOTE_PEERS")?;
+    let remote_addrs: Vec<Multiaddr> = remote_peers
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(Multiaddr::from_str)
+        .collect::<Result<_, _>>()?;
+
+    let local_key = identity::Keypair::generate_ed25519();
+
+    // wrap the raw TCP transport with cumulative inbound/outbound byte
+    // counters, exposed via `sinks`, so we can read back the traffic
+    // exchanged with the student over these dials
+    let (transport, sinks) = libp2p::bandwidth::BandwidthTransport::new(
+        tcp::tokio::Transport::new(tcp::Config::default()),
+    );
+    let sinks: Arc<BandwidthSinks> = sinks;
+
+    let gossipsub_config = gossipsub::ConfigBuilder::default()
+        .heartbeat_interval(Duration::from_secs(10))
+        .validation_mode(gossipsub::ValidationMode::Permissive)
+        .mesh_outbound_min(1)
+        .mesh_n_low(1)
+        .flood_publish(true)
+        .build()?;
+    let mut gossipsub = gossipsub::Behaviour::new(
+        gossipsub::MessageAuthenticity::Signed(local_key.clone()),
+        gossipsub_config,
+    )
+    .map_err(|e| anyhow::anyhow!(e))?;
+    let chat_topic = gossipsub::IdentTopic::new(CHAT_TOPIC);
+    gossipsub.subscribe(&chat_topic)?;
+
+    let mut swarm = SwarmBuilder::with_existing_identity(local_key)
+        .with_tokio()
+        .with_other_transport(|key| {
+            transport
+                .upgrade(libp2p::core::upgrade::Version::V1)
+                .authenticate(noise::Config::new(key)?)
+                .multiplex(yamux::Config::default())
+                .boxed()
+        })?
+        .with_behaviour(|_| Behaviour {
+            ping: ping::Behaviour::new(
+                ping::Config::new()
+                    .with_interval(Duration::from_secs(1))
+                    .with_timeout(Duration::from_secs(5)),
+            ),
+            gossipsub,
+        })?
+        .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+        .build();
+
+    // dial one more than the student's configured per-peer maximum: every
+    // dial targets the same address with the same local identity, so they
+    // all land on the same remote peer id from the student's point of view,
+    // and the surplus connection is the one that must be refused
+    let max_per_peer = env_u32("MAX_CONNECTIONS_PER_PEER", 2);
+    let dial_count = max_per_peer + 1;
+    for addr in remote_addrs.iter() {
+        for _ in 0..dial_count {
+            swarm.dial(addr.clone())?;
+        }
+    }
+
+    let mut accepted: u32 = 0;
+    let mut refused: u32 = 0;
+    let mut limit_checked = false;
+    // set once we've published the known-size payload, to the outbound byte
+    // count measured just before the publish call
+    let mut payload_baseline_out: Option<u64> = None;
+    let mut bandwidth_checked = false;
+    let mut ticks_since_payload: u32 = 0;
+    let mut timer = tokio::time::interval(TICK_INTERVAL);
+
+    let outcome = checker_core::with_stage_timeout(
+        STAGE,
+        Duration::from_secs(STAGE_TIMEOUT_SECS),
+        async {
+            loop {
+                tokio::select! {
+                    Some(event) = swarm.next() => match event {
+                        SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                            accepted += 1;
+                            println!("connected,{peer_id},{}", endpoint.get_remote_address());
+
+                            if payload_baseline_out.is_none() {
+                                let payload = UniversalConnectivityMessage {
+                                    from: peer_id.to_string(),
+                                    message: "x".repeat(BANDWIDTH_PAYLOAD_LEN),
+                                    timestamp: 0,
+                                };
+                                let mut buf = Vec::new();
+                                payload.encode(&mut buf)?;
+                                payload_baseline_out = Some(sinks.total_outbound());
+                                if let Err(error) = swarm.behaviour_mut().gossipsub.publish(chat_topic.clone(), buf) {
+                                    println!("error,failed to publish bandwidth payload: {error}");
+                                }
+                            }
+                        }
+                        SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                            if let Some(error) = cause {
+                                println!("error,{error}");
+                            } else {
+                                println!("closed,{peer_id}");
+                            }
+                        }
+                        SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
+                            // the student refusing a surplus connection past its
+                            // per-peer maximum surfaces here as a dial failure
+                            refused += 1;
+                            println!("error,dial to {peer_id:?} refused: {error}");
+                        }
+                        SwarmEvent::Behaviour(BehaviourEvent::Ping(ping::Event { peer, result, .. })) => {
+                            match result {
+                                Ok(rtt) => {
+                                    println!("ping,{peer},{} ms", rtt.as_millis());
+                                }
+                                Err(failure) => {
+                                    println!("error,{failure}");
+                                }
+                            }
+                        }
+                        _ => {}
+                    },
+                    _ = timer.tick() => {
+                        println!("limit,{accepted},{refused}");
+
+                        if !limit_checked && accepted + refused >= dial_count {
+                            limit_checked = true;
+                            let expected_refusals = dial_count - max_per_peer;
+                            let outcome = if refused >= expected_refusals {
+                                CheckOutcome::Pass
+                            } else {
+                                CheckOutcome::Fail {
+                                    reason: format!(
+                                        "expected at least {expected_refusals} of {dial_count} dials to be refused, saw {refused}"
+                                    ),
+                                }
+                            };
+                            checker_core::emit(STAGE, "limit", None, Some(format!("{accepted} accepted, {refused} refused (max {max_per_peer} per peer)")), Some(outcome));
+                        }
+
+                        let in_bytes = sinks.total_inbound();
+                        let out_bytes = sinks.total_outbound();
+                        println!("bandwidth,{in_bytes},{out_bytes}");
+
+                        if let Some(baseline) = payload_baseline_out {
+                            if !bandwidth_checked {
+                                ticks_since_payload += 1;
+                                let produced = out_bytes.saturating_sub(baseline);
+                                if produced >= BANDWIDTH_PAYLOAD_LEN as u64 {
+                                    bandwidth_checked = true;
+                                    checker_core::emit(STAGE, "bandwidth", None, Some(format!("{produced} bytes sent for a {BANDWIDTH_PAYLOAD_LEN}-byte payload ({in_bytes} in, {out_bytes} out)")), Some(CheckOutcome::Pass));
+                                } else if ticks_since_payload >= BANDWIDTH_CHECK_GRACE_TICKS {
+                                    bandwidth_checked = true;
+                                    checker_core::emit(STAGE, "bandwidth", None, Some(format!("only {produced} of {BANDWIDTH_PAYLOAD_LEN} expected payload bytes seen on the wire")), Some(CheckOutcome::Fail { reason: format!("expected at least {BANDWIDTH_PAYLOAD_LEN} bytes of outbound traffic after publishing the payload, saw {produced}") }));
+                                }
+                            }
+                        }
+
+                        if limit_checked && bandwidth_checked {
+                            return Ok(())
+                        }
+                    }
+                }
+            }
+        },
+    )
+    .await;
+
+    match outcome {
+        Ok(result) => result,
+        Err(_) => Ok(()),
+    }
+}
@@ -0,0 +1,155 @@
+use anyhow::Result;
+use checker_core::CheckOutcome;
+use futures::StreamExt;
+use libp2p::{
+    identify, identity,
+    multiaddr::Protocol,
+    ping,
+    swarm::{NetworkBehaviour, SwarmEvent},
+    Multiaddr, SwarmBuilder,
+};
+use libp2p_webrtc as webrtc;
+use std::{env, str::FromStr, time::Duration};
+
+const IDENTIFY_PROTOCOL_VERSION: &str = "/ipfs/id/1.0.0";
+const AGENT_VERSION: &str = "universal-connectivity/0.1.0";
+const STAGE: &str = "webrtc-checkpoint";
+// hard ceiling on the whole stage event loop, so a student node that
+// never emits the expected terminal event can't hang the checker forever
+const STAGE_TIMEOUT_SECS: u64 = 60;
+
+// Define a custom network behaviour that includes ping and identify, dialed
+// over the WebRTC-direct transport configured below
+#[derive(NetworkBehaviour)]
+struct Behaviour {
+    ping: ping::Behaviour,
+    identify: identify::Behaviour,
+}
+
+// extract the hex digest out of a multiaddr's `/certhash/<multibase>` component
+fn certhash(addr: &Multiaddr) -> Option<String> {
+    addr.iter().find_map(|p| match p {
+        Protocol::Certhash(hash) => Some(hash.to_string()),
+        _ => None,
+    })
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    checker_core::init(STAGE);
+
+    // the student's advertised /webrtc-direct multiaddr, certhash included
+    let remote_peers = env::var("REMOTE_PEERS")?;
+    let remote_addrs: Vec<Multiaddr> = remote_peers
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(Multiaddr::from_str)
+        .collect::<Result<_, _>>()?;
+
+    // the certhash we dialed with, so we can confirm the student reports the
+    // same one back over identify rather than a stale one from a previous run
+    let dialed_certhash = remote_addrs.iter().find_map(certhash);
+
+    let local_key = identity::Keypair::generate_ed25519();
+
+    let mut swarm = SwarmBuilder::with_existing_identity(local_key)
+        .with_tokio()
+        .with_other_transport(|key| {
+            Ok(webrtc::tokio::Transport::new(
+                key.clone(),
+                webrtc::tokio::Certificate::generate(&mut rand::thread_rng())?,
+            ))
+        })?
+        .with_behaviour(|key| Behaviour {
+            ping: ping::Behaviour::new(
+                ping::Config::new()
+                    .with_interval(Duration::from_secs(1))
+                    .with_timeout(Duration::from_secs(5)),
+            ),
+            identify: identify::Behaviour::new(
+                identify::Config::new(IDENTIFY_PROTOCOL_VERSION.to_string(), key.public())
+                    .with_agent_version(AGENT_VERSION.to_string()),
+            ),
+        })?
+        .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+        .build();
+
+    for addr in remote_addrs.into_iter() {
+        swarm.dial(addr)?;
+    }
+
+    let outcome = checker_core::with_stage_timeout(
+        STAGE,
+        Duration::from_secs(STAGE_TIMEOUT_SECS),
+        async {
+            loop {
+                tokio::select! {
+                    Some(event) = swarm.next() => match event {
+                        SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                            println!("connected,{peer_id},{}", endpoint.get_remote_address());
+                        }
+                        SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                            if let Some(error) = cause {
+                                println!("error,{error}");
+                            } else {
+                                println!("closed,{peer_id}");
+                            }
+                            return Ok(())
+                        }
+                        SwarmEvent::OutgoingConnectionError { error, .. } => {
+                            println!("error,{error}");
+                            return Ok(())
+                        }
+                        SwarmEvent::Behaviour(behaviour_event) => match behaviour_event {
+                            BehaviourEvent::Ping(ping::Event { peer, result, .. }) => {
+                                match result {
+                                    Ok(rtt) => {
+                                        println!("ping,{peer},{} ms", rtt.as_millis());
+                                        checker_core::emit(STAGE, "ping", Some(peer.to_string()), Some(format!("{} ms", rtt.as_millis())), Some(CheckOutcome::Pass));
+                                    }
+                                    Err(failure) => {
+                                        println!("error,{failure}");
+                                    }
+                                }
+                            }
+                            BehaviourEvent::Identify(identify_event) => {
+                                match identify_event {
+                                    identify::Event::Received { peer_id, info, .. } => {
+                                        println!("identify,{peer_id},{},{}", info.protocol_version, info.agent_version);
+
+                                        let advertised = info.listen_addrs.iter().find_map(certhash);
+                                        match (&dialed_certhash, &advertised) {
+                                            (Some(dialed), Some(seen)) if dialed == seen => {
+                                                println!("webrtc,{peer_id},{seen}");
+                                                checker_core::emit(STAGE, "webrtc", Some(peer_id.to_string()), Some(seen.clone()), Some(CheckOutcome::Pass));
+                                            }
+                                            _ => {
+                                                // a common student mistake: the cert is regenerated per
+                                                // run, so the advertised certhash no longer matches the
+                                                // one actually presented during the DTLS handshake
+                                                println!("error,certhash mismatch for {peer_id}");
+                                                checker_core::emit(STAGE, "webrtc", Some(peer_id.to_string()), Some("certhash mismatch".to_string()), Some(CheckOutcome::Fail { reason: "certhash mismatch".to_string() }));
+                                            }
+                                        }
+                                    }
+                                    identify::Event::Error { error, .. } => {
+                                        println!("error,{error}");
+                                    }
+                                    _ => {}
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        },
+    )
+    .await;
+
+    match outcome {
+        Ok(result) => result,
+        Err(_) => Ok(()),
+    }
+}
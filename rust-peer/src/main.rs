@@ -0,0 +1,1077 @@
+use anyhow::Result;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, StreamExt};
+use libp2p::{
+    dcutr, gossipsub, identify, identity, kad,
+    multiaddr::Protocol,
+    noise, ping, relay, rendezvous, request_response, tcp,
+    swarm::{behaviour::toggle::Toggle, NetworkBehaviour, Swarm, SwarmEvent},
+    yamux, Multiaddr, PeerId, StreamProtocol, SwarmBuilder,
+};
+use libp2p_webrtc as webrtc;
+use prost::Message;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    env,
+    hash::{Hash, Hasher},
+    io,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{interval, Interval};
+
+const IDENTIFY_PROTOCOL_VERSION: &str = "/ipfs/id/1.0.0";
+const AGENT_VERSION: &str = "universal-connectivity/0.1.0";
+const GOSSIPSUB_TOPICS: &[&str] = &[
+    "universal-connectivity",
+    "universal-connectivity-file",
+    "universal-connectivity-browser-peer-discovery",
+];
+const KADEMLIA_PROTOCOL_NAME: StreamProtocol = StreamProtocol::new("/ipfs/kad/1.0.0");
+const KADEMLIA_QUERY_TIMEOUT: u64 = 60;
+const IDENTITY_KEY_PATH: &str = "/app/key";
+const WEBRTC_CERT_PATH: &str = "/app/cert.pem";
+const FILE_PROTOCOL_NAME: StreamProtocol = StreamProtocol::new("/universal-connectivity-file/1");
+// keeps a single file response from ever needing one giant in-memory frame;
+// also what lets a transfer dwarf the gossipsub message-size limit, since
+// none of this travels over gossipsub in the first place
+const FILE_CHUNK_SIZE: usize = 16 * 1024;
+const DEFAULT_RENDEZVOUS_NAMESPACE: &str = "universal-connectivity";
+// workshop sessions are short-lived, so we re-discover often rather than
+// waiting out a registration's full TTL
+const RENDEZVOUS_DISCOVER_INTERVAL: u64 = 30;
+const COMMAND_CHANNEL_SIZE: usize = 32;
+const EVENT_CHANNEL_SIZE: usize = 32;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UniversalConnectivityMessage {
+    #[prost(string, tag = "1")]
+    pub from: String,
+    #[prost(string, tag = "2")]
+    pub message: String,
+    #[prost(int64, tag = "3")]
+    pub timestamp: i64,
+    #[prost(enumeration = "MessageType", tag = "4")]
+    pub message_type: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum MessageType {
+    Chat = 0,
+    File = 1,
+    BrowserPeerDiscovery = 2,
+}
+
+// Define a custom network behaviour that includes ping, identify, gossipsub,
+// kademlia, file-transfer, rendezvous, and relay client + DCUtR functionality
+// for NAT traversal
+#[derive(NetworkBehaviour)]
+struct Behaviour {
+    ping: ping::Behaviour,
+    identify: identify::Behaviour,
+    gossipsub: gossipsub::Behaviour,
+    kademlia: kad::Behaviour<kad::store::MemoryStore>,
+    file_transfer: request_response::Behaviour<FileExchangeCodec>,
+    rendezvous: rendezvous::client::Behaviour,
+    // only active when RENDEZVOUS_SERVER is set, so a workshop participant
+    // can stand their own node up as the rendezvous point for the others
+    rendezvous_server: Toggle<rendezvous::server::Behaviour>,
+    relay_client: relay::client::Behaviour,
+    dcutr: dcutr::Behaviour,
+}
+
+#[derive(Clone, Debug)]
+struct FileRequest {
+    id: String,
+}
+
+#[derive(Clone, Debug)]
+struct FileResponse {
+    data: Vec<u8>,
+}
+
+#[derive(Clone, Default)]
+struct FileExchangeCodec;
+
+#[async_trait::async_trait]
+impl request_response::Codec for FileExchangeCodec {
+    type Protocol = StreamProtocol;
+    type Request = FileRequest;
+    type Response = FileResponse;
+
+    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut len_buf = [0u8; 4];
+        io.read_exact(&mut len_buf).await?;
+        let mut id_buf = vec![0u8; u32::from_be_bytes(len_buf) as usize];
+        io.read_exact(&mut id_buf).await?;
+        let id = String::from_utf8(id_buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(FileRequest { id })
+    }
+
+    async fn read_response<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        // each chunk is length-prefixed; a zero-length chunk marks the end,
+        // so an empty file is just an immediate terminator
+        let mut data = Vec::new();
+        loop {
+            let mut len_buf = [0u8; 4];
+            io.read_exact(&mut len_buf).await?;
+            let len = u32::from_be_bytes(len_buf) as usize;
+            if len == 0 {
+                break;
+            }
+            let mut chunk = vec![0u8; len];
+            io.read_exact(&mut chunk).await?;
+            data.extend_from_slice(&chunk);
+        }
+        Ok(FileResponse { data })
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        FileRequest { id }: Self::Request,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let id = id.into_bytes();
+        io.write_all(&(id.len() as u32).to_be_bytes()).await?;
+        io.write_all(&id).await?;
+        io.close().await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        FileResponse { data }: Self::Response,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        for chunk in data.chunks(FILE_CHUNK_SIZE) {
+            io.write_all(&(chunk.len() as u32).to_be_bytes()).await?;
+            io.write_all(chunk).await?;
+        }
+        io.write_all(&0u32.to_be_bytes()).await?;
+        io.close().await
+    }
+}
+
+fn is_relayed(addr: &Multiaddr) -> bool {
+    addr.iter().any(|p| p == Protocol::P2pCircuit)
+}
+
+async fn read_identity() -> Result<identity::Keypair> {
+    let key_path = PathBuf::from(IDENTITY_KEY_PATH);
+    let bytes = tokio::fs::read(&key_path).await?;
+    Ok(identity::Keypair::from_protobuf_encoding(&bytes)?)
+}
+
+// persisted alongside the node identity so the certhash we advertise stays
+// stable across restarts instead of changing every run
+async fn read_or_generate_webrtc_cert() -> Result<webrtc::tokio::Certificate> {
+    let cert_path = PathBuf::from(WEBRTC_CERT_PATH);
+    if let Ok(pem) = tokio::fs::read_to_string(&cert_path).await {
+        return Ok(webrtc::tokio::Certificate::from_pem(&pem)?);
+    }
+
+    let cert = webrtc::tokio::Certificate::generate(&mut rand::thread_rng())?;
+    tokio::fs::write(&cert_path, cert.serialize_pem()).await?;
+    Ok(cert)
+}
+
+fn message_id(msg: &gossipsub::Message) -> gossipsub::MessageId {
+    let mut s = DefaultHasher::new();
+    msg.data.hash(&mut s);
+    gossipsub::MessageId::from(s.finish().to_string())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn split_address(addr: Multiaddr) -> Option<(PeerId, Multiaddr)> {
+    let mut base_addr = Multiaddr::empty();
+    let mut peer_id = None;
+
+    for protocol in addr.into_iter() {
+        match protocol {
+            Protocol::P2p(id) => {
+                peer_id = Some(id);
+                break;
+            }
+            _ => {
+                base_addr.push(protocol);
+            }
+        }
+    }
+
+    peer_id.map(|id| (id, base_addr))
+}
+
+// requests the event loop can act on, each carrying a `oneshot::Sender` the
+// caller awaits for the outcome; this is what lets control live outside the
+// process entry point instead of being hard-wired into `main`
+enum Command {
+    Dial {
+        addr: Multiaddr,
+        sender: oneshot::Sender<Result<(), libp2p::swarm::DialError>>,
+    },
+    PublishChat {
+        topic: String,
+        message: String,
+        sender: oneshot::Sender<Result<(), gossipsub::PublishError>>,
+    },
+    GetClosestPeers {
+        peer_id: PeerId,
+        sender: oneshot::Sender<Vec<PeerId>>,
+    },
+    StartProviding {
+        key: kad::RecordKey,
+        sender: oneshot::Sender<Result<(), kad::store::Error>>,
+    },
+    GetProviders {
+        key: kad::RecordKey,
+        sender: oneshot::Sender<Vec<PeerId>>,
+    },
+    PutRecord {
+        key: kad::RecordKey,
+        value: Vec<u8>,
+        sender: oneshot::Sender<Result<(), kad::store::Error>>,
+    },
+    GetRecord {
+        key: kad::RecordKey,
+        sender: oneshot::Sender<Option<Vec<u8>>>,
+    },
+    Subscribe {
+        topic: gossipsub::IdentTopic,
+        sender: oneshot::Sender<Result<bool, gossipsub::SubscriptionError>>,
+    },
+    ListPeers {
+        sender: oneshot::Sender<Vec<PeerId>>,
+    },
+    // makes `data` available for other peers to fetch by `id`, and announces
+    // it on the file topic so they know to ask
+    AnnounceFile {
+        id: String,
+        data: Vec<u8>,
+        sender: oneshot::Sender<Result<(), gossipsub::PublishError>>,
+    },
+}
+
+// pushed out of the event loop for activity nobody asked for directly -
+// inbound gossip, identify from a freshly-connected peer, DHT convergence -
+// as opposed to the request/response traffic that flows through `Command`
+enum Event {
+    Message {
+        from: String,
+        topic: String,
+        message: String,
+    },
+    Identified {
+        peer_id: PeerId,
+        protocol_version: String,
+        agent_version: String,
+    },
+    KademliaBootstrapped,
+}
+
+// a cheaply-cloneable handle for driving the swarm from anywhere (stdin
+// reader, a future RPC server, ...) without owning it
+#[derive(Clone)]
+struct Client {
+    command_sender: mpsc::Sender<Command>,
+}
+
+impl Client {
+    async fn dial(&self, addr: Multiaddr) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.command_sender
+            .send(Command::Dial { addr, sender })
+            .await?;
+        Ok(receiver.await??)
+    }
+
+    async fn publish_chat(&self, topic: String, message: String) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.command_sender
+            .send(Command::PublishChat {
+                topic,
+                message,
+                sender,
+            })
+            .await?;
+        Ok(receiver.await??)
+    }
+
+    async fn get_closest_peers(&self, peer_id: PeerId) -> Result<Vec<PeerId>> {
+        let (sender, receiver) = oneshot::channel();
+        self.command_sender
+            .send(Command::GetClosestPeers { peer_id, sender })
+            .await?;
+        Ok(receiver.await?)
+    }
+
+    async fn start_providing(&self, key: kad::RecordKey) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.command_sender
+            .send(Command::StartProviding { key, sender })
+            .await?;
+        Ok(receiver.await??)
+    }
+
+    async fn get_providers(&self, key: kad::RecordKey) -> Result<Vec<PeerId>> {
+        let (sender, receiver) = oneshot::channel();
+        self.command_sender
+            .send(Command::GetProviders { key, sender })
+            .await?;
+        Ok(receiver.await?)
+    }
+
+    async fn put_record(&self, key: kad::RecordKey, value: Vec<u8>) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.command_sender
+            .send(Command::PutRecord { key, value, sender })
+            .await?;
+        Ok(receiver.await??)
+    }
+
+    async fn get_record(&self, key: kad::RecordKey) -> Result<Option<Vec<u8>>> {
+        let (sender, receiver) = oneshot::channel();
+        self.command_sender
+            .send(Command::GetRecord { key, sender })
+            .await?;
+        Ok(receiver.await?)
+    }
+
+    async fn subscribe(&self, topic: gossipsub::IdentTopic) -> Result<bool> {
+        let (sender, receiver) = oneshot::channel();
+        self.command_sender
+            .send(Command::Subscribe { topic, sender })
+            .await?;
+        Ok(receiver.await??)
+    }
+
+    async fn list_peers(&self) -> Result<Vec<PeerId>> {
+        let (sender, receiver) = oneshot::channel();
+        self.command_sender
+            .send(Command::ListPeers { sender })
+            .await?;
+        Ok(receiver.await?)
+    }
+
+    async fn announce_file(&self, id: String, data: Vec<u8>) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.command_sender
+            .send(Command::AnnounceFile { id, data, sender })
+            .await?;
+        Ok(receiver.await??)
+    }
+}
+
+// owns the swarm and drives it; everything that used to live inline in
+// `main`'s run loop now lives here, reachable only through `Command`s sent
+// over the channel paired with it
+struct EventLoop {
+    swarm: Swarm<Behaviour>,
+    local_peer_id: PeerId,
+    command_receiver: mpsc::Receiver<Command>,
+    event_sender: mpsc::Sender<Event>,
+    pending_closest_peers: HashMap<kad::QueryId, oneshot::Sender<Vec<PeerId>>>,
+    pending_get_providers: HashMap<kad::QueryId, oneshot::Sender<Vec<PeerId>>>,
+    pending_get_records: HashMap<kad::QueryId, oneshot::Sender<Option<Vec<u8>>>>,
+    // the most recent non-relay address each peer connected on, printed as
+    // the direct address once DCUtR reports a successful hole punch
+    direct_addrs: HashMap<PeerId, Multiaddr>,
+    // files we've announced, keyed by the id we advertised on the file
+    // topic, so an inbound request can be served straight out of memory
+    files: HashMap<String, Vec<u8>>,
+    // lets a completed file_transfer response be tied back to the id it was
+    // requested for, since the response itself doesn't carry one
+    pending_file_requests: HashMap<request_response::OutboundRequestId, String>,
+    // peer ids of the configured rendezvous points; registered with and
+    // re-discovered through on every connection and on `discover_interval`
+    rendezvous_points: Vec<PeerId>,
+    rendezvous_namespace: rendezvous::Namespace,
+    // each rendezvous point hands back a cookie that bounds the next
+    // discover call to registrations we haven't seen yet
+    rendezvous_cookies: HashMap<PeerId, rendezvous::Cookie>,
+    discover_interval: Interval,
+}
+
+impl EventLoop {
+    fn new(
+        swarm: Swarm<Behaviour>,
+        local_peer_id: PeerId,
+        rendezvous_points: Vec<PeerId>,
+        rendezvous_namespace: rendezvous::Namespace,
+    ) -> (Client, Self, mpsc::Receiver<Event>) {
+        let (command_sender, command_receiver) = mpsc::channel(COMMAND_CHANNEL_SIZE);
+        let (event_sender, event_receiver) = mpsc::channel(EVENT_CHANNEL_SIZE);
+
+        (
+            Client { command_sender },
+            EventLoop {
+                swarm,
+                local_peer_id,
+                command_receiver,
+                event_sender,
+                pending_closest_peers: HashMap::new(),
+                pending_get_providers: HashMap::new(),
+                pending_get_records: HashMap::new(),
+                direct_addrs: HashMap::new(),
+                files: HashMap::new(),
+                pending_file_requests: HashMap::new(),
+                rendezvous_points,
+                rendezvous_namespace,
+                rendezvous_cookies: HashMap::new(),
+                discover_interval: interval(Duration::from_secs(RENDEZVOUS_DISCOVER_INTERVAL)),
+            },
+            event_receiver,
+        )
+    }
+
+    async fn run(mut self) -> Result<()> {
+        loop {
+            tokio::select! {
+                Some(event) = self.swarm.next() => self.handle_swarm_event(event),
+                command = self.command_receiver.recv() => match command {
+                    Some(command) => self.handle_command(command),
+                    // the Client was dropped, so no more commands are coming;
+                    // ping/identify/discover traffic alone would otherwise
+                    // keep this loop (and the task awaiting it) alive forever
+                    None => return Ok(()),
+                },
+                _ = self.discover_interval.tick() => self.discover_rendezvous(),
+            }
+        }
+    }
+
+    // re-issues a `discover` against every configured rendezvous point,
+    // picking up from each one's last cookie so we only hear about
+    // registrations we haven't already seen
+    fn discover_rendezvous(&mut self) {
+        for rendezvous_point in self.rendezvous_points.clone() {
+            let cookie = self.rendezvous_cookies.get(&rendezvous_point).cloned();
+            self.swarm.behaviour_mut().rendezvous.discover(
+                Some(self.rendezvous_namespace.clone()),
+                cookie,
+                None,
+                rendezvous_point,
+            );
+        }
+    }
+
+    fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::Dial { addr, sender } => {
+                let _ = sender.send(self.swarm.dial(addr));
+            }
+            Command::PublishChat {
+                topic,
+                message,
+                sender,
+            } => {
+                let msg = UniversalConnectivityMessage {
+                    from: self.local_peer_id.to_string(),
+                    message,
+                    timestamp: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs() as i64,
+                    message_type: MessageType::Chat as i32,
+                };
+                let result = self
+                    .swarm
+                    .behaviour_mut()
+                    .gossipsub
+                    .publish(gossipsub::IdentTopic::new(topic), msg.encode_to_vec())
+                    .map(|_| ());
+                let _ = sender.send(result);
+            }
+            Command::GetClosestPeers { peer_id, sender } => {
+                let query_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .get_closest_peers(peer_id);
+                self.pending_closest_peers.insert(query_id, sender);
+            }
+            Command::StartProviding { key, sender } => {
+                // `start_providing` only fails synchronously if the local
+                // store rejects the key; the network-level announcement
+                // result arrives later as a `QueryResult::StartProviding`
+                let result = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .start_providing(key)
+                    .map(|_| ());
+                let _ = sender.send(result);
+            }
+            Command::Subscribe { topic, sender } => {
+                let _ = sender.send(self.swarm.behaviour_mut().gossipsub.subscribe(&topic));
+            }
+            Command::GetProviders { key, sender } => {
+                let query_id = self.swarm.behaviour_mut().kademlia.get_providers(key);
+                self.pending_get_providers.insert(query_id, sender);
+            }
+            Command::PutRecord { key, value, sender } => {
+                let result = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .put_record(kad::Record::new(key, value), kad::Quorum::One)
+                    .map(|_| ());
+                let _ = sender.send(result);
+            }
+            Command::GetRecord { key, sender } => {
+                let query_id = self.swarm.behaviour_mut().kademlia.get_record(key);
+                self.pending_get_records.insert(query_id, sender);
+            }
+            Command::ListPeers { sender } => {
+                let _ = sender.send(self.swarm.connected_peers().copied().collect());
+            }
+            Command::AnnounceFile { id, data, sender } => {
+                self.files.insert(id.clone(), data);
+                let msg = UniversalConnectivityMessage {
+                    from: self.local_peer_id.to_string(),
+                    message: id,
+                    timestamp: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs() as i64,
+                    message_type: MessageType::File as i32,
+                };
+                let result = self
+                    .swarm
+                    .behaviour_mut()
+                    .gossipsub
+                    .publish(
+                        gossipsub::IdentTopic::new("universal-connectivity-file"),
+                        msg.encode_to_vec(),
+                    )
+                    .map(|_| ());
+                let _ = sender.send(result);
+            }
+        }
+    }
+
+    fn handle_swarm_event(&mut self, event: SwarmEvent<BehaviourEvent>) {
+        match event {
+            SwarmEvent::NewListenAddr { address, .. } => {
+                // printed the same way for every transport, so a browser
+                // peer can bootstrap off the /webrtc-direct line just
+                // like it would off a /tcp or /quic one
+                println!("listening,{address}");
+            }
+            SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                let addr = endpoint.get_remote_address();
+                if is_relayed(addr) {
+                    println!("relayed,{peer_id},{addr}");
+                } else {
+                    println!("connected,{peer_id},{addr}");
+                    self.direct_addrs.insert(peer_id, addr.clone());
+                }
+                // register and discover as soon as we connect, rather than
+                // waiting for the next `discover_interval` tick
+                if self.rendezvous_points.contains(&peer_id) {
+                    if let Err(error) =
+                        self.swarm
+                            .behaviour_mut()
+                            .rendezvous
+                            .register(self.rendezvous_namespace.clone(), peer_id, None)
+                    {
+                        println!("error,{error}");
+                    }
+                    self.swarm.behaviour_mut().rendezvous.discover(
+                        Some(self.rendezvous_namespace.clone()),
+                        None,
+                        None,
+                        peer_id,
+                    );
+                }
+            }
+            SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                if let Some(error) = cause {
+                    println!("error,{error}");
+                } else {
+                    println!("closed,{peer_id}");
+                }
+            }
+            SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
+                println!("incoming,{local_addr},{send_back_addr}");
+            }
+            SwarmEvent::OutgoingConnectionError { error, .. } => {
+                println!("error,{error}");
+            }
+            SwarmEvent::Behaviour(behaviour_event) => self.handle_behaviour_event(behaviour_event),
+            _ => {}
+        }
+    }
+
+    fn handle_behaviour_event(&mut self, event: BehaviourEvent) {
+        match event {
+            BehaviourEvent::Ping(ping::Event { peer, result, .. }) => match result {
+                Ok(rtt) => {
+                    println!("ping,{peer},{} ms", rtt.as_millis());
+                }
+                Err(failure) => {
+                    println!("error,{failure}");
+                }
+            },
+            BehaviourEvent::Identify(identify_event) => match identify_event {
+                identify::Event::Received { peer_id, info, .. } => {
+                    println!(
+                        "identify,{peer_id},{},{}",
+                        info.protocol_version, info.agent_version
+                    );
+                    let _ = self.event_sender.try_send(Event::Identified {
+                        peer_id,
+                        protocol_version: info.protocol_version,
+                        agent_version: info.agent_version,
+                    });
+                }
+                identify::Event::Error { error, .. } => {
+                    println!("error,{error}");
+                }
+                _ => {}
+            },
+            BehaviourEvent::Gossipsub(gossipsub_event) => match gossipsub_event {
+                gossipsub::Event::Message { message, .. } => {
+                    if let Ok(msg) = UniversalConnectivityMessage::decode(&message.data[..]) {
+                        println!("msg,{},{},{}", msg.from, message.topic, msg.message);
+                        // the announcement only carries the file's id; fetch
+                        // the bytes from the announcer over request-response
+                        // instead of expecting them on the gossipsub topic
+                        if msg.message_type() == MessageType::File {
+                            println!("file,announce,{},{}", msg.from, msg.message);
+                            if let Ok(peer_id) = PeerId::from_str(&msg.from) {
+                                let request_id = self.swarm.behaviour_mut().file_transfer.send_request(
+                                    &peer_id,
+                                    FileRequest {
+                                        id: msg.message.clone(),
+                                    },
+                                );
+                                self.pending_file_requests.insert(request_id, msg.message.clone());
+                            }
+                        }
+                        let _ = self.event_sender.try_send(Event::Message {
+                            from: msg.from,
+                            topic: message.topic.to_string(),
+                            message: msg.message,
+                        });
+                    } else {
+                        println!("error,{}", message.topic);
+                    }
+                }
+                gossipsub::Event::Subscribed { peer_id, topic } => {
+                    println!("subscribe,{peer_id},{topic}");
+                }
+                gossipsub::Event::Unsubscribed { peer_id, topic } => {
+                    println!("unsubscribe,{peer_id},{topic}");
+                }
+                _ => {}
+            },
+            BehaviourEvent::Kademlia(kad_event) => match kad_event {
+                kad::Event::OutboundQueryProgressed { id, result, .. } => match result {
+                    kad::QueryResult::Bootstrap(Ok(kad::BootstrapOk { num_remaining, .. })) => {
+                        if num_remaining == 0 {
+                            println!("kademlia,bootstrap");
+                            let _ = self.event_sender.try_send(Event::KademliaBootstrapped);
+                        }
+                    }
+                    kad::QueryResult::Bootstrap(Err(error)) => {
+                        println!("error,{error}");
+                    }
+                    kad::QueryResult::GetClosestPeers(Ok(kad::GetClosestPeersOk { peers, .. })) => {
+                        println!("kademlia,closestpeers,{}", peers.len());
+                        if let Some(sender) = self.pending_closest_peers.remove(&id) {
+                            let _ = sender.send(peers);
+                        }
+                    }
+                    kad::QueryResult::GetClosestPeers(Err(error)) => {
+                        println!("error,{error}");
+                        if let Some(sender) = self.pending_closest_peers.remove(&id) {
+                            let _ = sender.send(Vec::new());
+                        }
+                    }
+                    kad::QueryResult::StartProviding(Ok(kad::AddProviderOk { key })) => {
+                        println!("kademlia,startproviding,{},ok", hex(key.as_ref()));
+                    }
+                    kad::QueryResult::StartProviding(Err(error)) => {
+                        println!("error,{error}");
+                    }
+                    kad::QueryResult::GetProviders(Ok(kad::GetProvidersOk::FoundProviders { key, providers })) => {
+                        println!("kademlia,providers,{},{}", hex(key.as_ref()), providers.len());
+                        if let Some(sender) = self.pending_get_providers.remove(&id) {
+                            let _ = sender.send(providers.into_iter().collect());
+                        }
+                    }
+                    kad::QueryResult::GetProviders(Ok(kad::GetProvidersOk::FinishedWithNoAdditionalRecord {
+                        ..
+                    })) => {
+                        // still pending here means no FoundProviders ever arrived
+                        if let Some(sender) = self.pending_get_providers.remove(&id) {
+                            let _ = sender.send(Vec::new());
+                        }
+                    }
+                    kad::QueryResult::GetProviders(Err(error)) => {
+                        println!("error,{error}");
+                        if let Some(sender) = self.pending_get_providers.remove(&id) {
+                            let _ = sender.send(Vec::new());
+                        }
+                    }
+                    kad::QueryResult::PutRecord(Ok(kad::PutRecordOk { key })) => {
+                        println!("kademlia,putrecord,{},ok", hex(key.as_ref()));
+                    }
+                    kad::QueryResult::PutRecord(Err(error)) => {
+                        println!("error,{error}");
+                    }
+                    kad::QueryResult::GetRecord(Ok(kad::GetRecordOk::FoundRecord(kad::PeerRecord {
+                        record,
+                        ..
+                    }))) => {
+                        println!(
+                            "kademlia,getrecord,{},{}",
+                            hex(record.key.as_ref()),
+                            String::from_utf8_lossy(&record.value)
+                        );
+                        if let Some(sender) = self.pending_get_records.remove(&id) {
+                            let _ = sender.send(Some(record.value));
+                        }
+                    }
+                    kad::QueryResult::GetRecord(Ok(kad::GetRecordOk::FinishedWithNoAdditionalRecord {
+                        ..
+                    })) => {
+                        if let Some(sender) = self.pending_get_records.remove(&id) {
+                            let _ = sender.send(None);
+                        }
+                    }
+                    kad::QueryResult::GetRecord(Err(error)) => {
+                        println!("error,{error}");
+                        if let Some(sender) = self.pending_get_records.remove(&id) {
+                            let _ = sender.send(None);
+                        }
+                    }
+                    _ => {}
+                },
+                _ => {}
+            },
+            BehaviourEvent::FileTransfer(file_event) => match file_event {
+                request_response::Event::Message { message, .. } => match message {
+                    request_response::Message::Request { request, channel, .. } => {
+                        let data = self.files.get(&request.id).cloned().unwrap_or_default();
+                        let _ = self
+                            .swarm
+                            .behaviour_mut()
+                            .file_transfer
+                            .send_response(channel, FileResponse { data });
+                    }
+                    request_response::Message::Response { request_id, response } => {
+                        if let Some(id) = self.pending_file_requests.remove(&request_id) {
+                            println!("file,received,{},{}", id, response.data.len());
+                        }
+                    }
+                },
+                request_response::Event::OutboundFailure { request_id, error, .. } => {
+                    self.pending_file_requests.remove(&request_id);
+                    println!("error,{error}");
+                }
+                request_response::Event::InboundFailure { error, .. } => {
+                    println!("error,{error}");
+                }
+                request_response::Event::ResponseSent { .. } => {}
+            },
+            BehaviourEvent::Rendezvous(rendezvous_event) => match rendezvous_event {
+                rendezvous::client::Event::Registered { namespace, .. } => {
+                    println!("rendezvous,registered,{namespace}");
+                }
+                rendezvous::client::Event::RegisterFailed { rendezvous_node, namespace, error } => {
+                    println!("error,rendezvous register with {rendezvous_node} under {namespace} failed: {error:?}");
+                }
+                rendezvous::client::Event::Discovered { rendezvous_node, registrations, cookie } => {
+                    self.rendezvous_cookies.insert(rendezvous_node, cookie);
+                    for registration in registrations {
+                        let peer = registration.record.peer_id();
+                        for addr in registration.record.addresses() {
+                            println!("rendezvous,discovered,{peer},{addr}");
+                            self.swarm.behaviour_mut().kademlia.add_address(&peer, addr.clone());
+                            let _ = self.swarm.dial(addr.clone());
+                        }
+                    }
+                }
+                rendezvous::client::Event::DiscoverFailed { rendezvous_node, error, .. } => {
+                    println!("error,rendezvous discover from {rendezvous_node} failed: {error:?}");
+                }
+                rendezvous::client::Event::Expired { peer } => {
+                    println!("error,rendezvous registration for {peer} expired");
+                }
+            },
+            BehaviourEvent::RendezvousServer(_) => {}
+            BehaviourEvent::RelayClient(relay_event) => match relay_event {
+                relay::client::Event::ReservationReqAccepted { relay_peer_id, .. } => {
+                    println!("reservation,{relay_peer_id}");
+                }
+                relay::client::Event::OutboundCircuitEstablished { relay_peer_id, .. } => {
+                    println!("relayed,{relay_peer_id}");
+                }
+                _ => {}
+            },
+            BehaviourEvent::Dcutr(dcutr::Event { remote_peer_id, result }) => {
+                // the simultaneous-open negotiation (who dials first, the
+                // shared nonce exchanged over the relayed connection) is
+                // handled internally by the dcutr behaviour; we only see
+                // the outcome here
+                match result {
+                    Ok(_) => {
+                        if let Some(addr) = self.direct_addrs.get(&remote_peer_id) {
+                            println!("dcutr,{remote_peer_id},success,{addr}");
+                        } else {
+                            // dcutr reports success before the direct
+                            // ConnectionEstablished event lands; the
+                            // SwarmEvent handler above will still
+                            // print `connected,...` for it
+                            println!("dcutr,{remote_peer_id},success,pending");
+                        }
+                    }
+                    Err(error) => {
+                        // direct upgrade failed; we're stuck on the relayed path
+                        println!("dcutr,{remote_peer_id},failed,{error}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // parse the addresses to listen on from the environment variable; may
+    // include /webrtc-direct multiaddrs so browser peers can dial in
+    let mut listen_on: Vec<Multiaddr> = Vec::default();
+    if let Ok(listen_addrs) = env::var("LISTEN_ADDRS") {
+        listen_on = listen_addrs
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(Multiaddr::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+    }
+
+    // parse the bootstrap peer addresses from the environment variable
+    let mut bootstrap_addrs: Vec<Multiaddr> = Vec::default();
+    if let Ok(bootstrap_peers) = env::var("BOOTSTRAP_PEERS") {
+        bootstrap_addrs = bootstrap_peers
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(Multiaddr::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+    }
+
+    // relay addresses to reserve a circuit slot on, so the node becomes
+    // reachable at `<relay_addr>/p2p-circuit` even when it's behind a NAT
+    let mut relay_addrs: Vec<Multiaddr> = Vec::default();
+    if let Ok(relay_peers) = env::var("RELAY_ADDRS") {
+        relay_addrs = relay_peers
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(Multiaddr::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+    }
+
+    // rendezvous points to register with and discover peers through; faster
+    // to converge than waiting on Kademlia bootstrap for a short-lived
+    // workshop session
+    let mut rendezvous_addrs: Vec<Multiaddr> = Vec::default();
+    if let Ok(rendezvous_peers) = env::var("RENDEZVOUS_ADDRS") {
+        rendezvous_addrs = rendezvous_peers
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(Multiaddr::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+    }
+
+    let rendezvous_namespace = rendezvous::Namespace::new(
+        env::var("RENDEZVOUS_NAMESPACE").unwrap_or_else(|_| DEFAULT_RENDEZVOUS_NAMESPACE.to_string()),
+    )?;
+
+    let local_key = read_identity().await?;
+    let local_peer_id = local_key.public().to_peer_id();
+    let webrtc_cert = read_or_generate_webrtc_cert().await?;
+
+    // Create a Gossipsub configuration
+    let gossipsub_config = gossipsub::ConfigBuilder::default()
+        .heartbeat_interval(Duration::from_secs(10))
+        .validation_mode(gossipsub::ValidationMode::Strict)
+        .message_id_fn(message_id)
+        .mesh_outbound_min(1)
+        .mesh_n_low(1)
+        .flood_publish(true)
+        .build()?;
+
+    let mut gossipsub = gossipsub::Behaviour::new(
+        gossipsub::MessageAuthenticity::Signed(local_key.clone()),
+        gossipsub_config,
+    )
+    .map_err(|e| anyhow::anyhow!(e))?;
+
+    for topic in GOSSIPSUB_TOPICS {
+        let topic = gossipsub::IdentTopic::new(*topic);
+        gossipsub.subscribe(&topic)?;
+    }
+
+    let mut kad_config = kad::Config::new(KADEMLIA_PROTOCOL_NAME);
+    kad_config.set_query_timeout(Duration::from_secs(KADEMLIA_QUERY_TIMEOUT));
+
+    let store = kad::store::MemoryStore::new(local_peer_id);
+    let mut kademlia = kad::Behaviour::with_config(local_peer_id, store, kad_config);
+    kademlia.set_mode(Some(kad::Mode::Server));
+
+    let has_bootstrap_peers = !bootstrap_addrs.is_empty();
+    for addr in bootstrap_addrs.into_iter() {
+        if let Some((peer_id, peer_addr)) = split_address(addr) {
+            println!("bootstrap,{peer_id},{peer_addr}");
+            kademlia.add_address(&peer_id, peer_addr);
+        }
+    }
+
+    let mut swarm = SwarmBuilder::with_existing_identity(local_key)
+        .with_tokio()
+        .with_tcp(
+            tcp::Config::default(),
+            noise::Config::new,
+            yamux::Config::default,
+        )?
+        .with_quic()
+        .with_other_transport(|key| Ok(webrtc::tokio::Transport::new(key.clone(), webrtc_cert)))?
+        .with_relay_client(noise::Config::new, yamux::Config::default)?
+        .with_behaviour(|key, relay_client| Behaviour {
+            ping: ping::Behaviour::new(
+                ping::Config::new()
+                    .with_interval(Duration::from_secs(1))
+                    .with_timeout(Duration::from_secs(5)),
+            ),
+            identify: identify::Behaviour::new(
+                identify::Config::new(IDENTIFY_PROTOCOL_VERSION.to_string(), key.public())
+                    .with_agent_version(AGENT_VERSION.to_string()),
+            ),
+            gossipsub,
+            kademlia,
+            file_transfer: request_response::Behaviour::new(
+                [(FILE_PROTOCOL_NAME, request_response::ProtocolSupport::Full)],
+                request_response::Config::default(),
+            ),
+            rendezvous: rendezvous::client::Behaviour::new(key.clone()),
+            rendezvous_server: if env::var("RENDEZVOUS_SERVER").is_ok() {
+                Some(rendezvous::server::Behaviour::new(rendezvous::server::Config::default())).into()
+            } else {
+                None.into()
+            },
+            relay_client,
+            dcutr: dcutr::Behaviour::new(key.public().to_peer_id()),
+        })?
+        .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+        .build();
+
+    if has_bootstrap_peers {
+        swarm.behaviour_mut().kademlia.bootstrap()?;
+    }
+
+    for addr in listen_on.into_iter() {
+        swarm.listen_on(addr)?;
+    }
+
+    // reserve a slot on each relay so we become reachable at
+    // `<relay_addr>/p2p-circuit`
+    for addr in relay_addrs.into_iter() {
+        swarm.listen_on(addr.with(Protocol::P2pCircuit))?;
+    }
+
+    // dial every rendezvous point so registration and discovery kick off as
+    // soon as the connection is established
+    let mut rendezvous_points: Vec<PeerId> = Vec::new();
+    for addr in rendezvous_addrs.into_iter() {
+        if let Some((peer_id, _)) = split_address(addr.clone()) {
+            rendezvous_points.push(peer_id);
+        }
+        swarm.dial(addr)?;
+    }
+
+    // `_event_receiver` isn't drained by anything yet - inbound chat/identify
+    // activity still reaches the terminal through the plain println!s in the
+    // event loop, not through this channel
+    let (client, event_loop, _event_receiver) =
+        EventLoop::new(swarm, local_peer_id, rendezvous_points, rendezvous_namespace);
+    let event_loop_handle = tokio::spawn(event_loop.run());
+
+    let mut stdin = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = stdin.next_line().await? {
+        handle_line(&client, line.trim()).await;
+    }
+
+    drop(client);
+    event_loop_handle.await?
+}
+
+// a typed line is either a slash-command mapped onto an existing swarm
+// operation, or plain text published as a chat message
+async fn handle_line(client: &Client, line: &str) {
+    if line.is_empty() {
+        return;
+    }
+
+    let mut parts = line.split_whitespace();
+    match parts.next() {
+        Some("/dial") => match parts.next().map(Multiaddr::from_str) {
+            Some(Ok(addr)) => {
+                if let Err(error) = client.dial(addr).await {
+                    println!("error,{error}");
+                }
+            }
+            Some(Err(error)) => println!("error,{error}"),
+            None => println!("error,usage: /dial <multiaddr>"),
+        },
+        Some("/peers") => match client.list_peers().await {
+            Ok(peers) => {
+                for peer in peers {
+                    println!("peer,{peer}");
+                }
+            }
+            Err(error) => println!("error,{error}"),
+        },
+        Some("/findpeers") => match parts.next().map(PeerId::from_str) {
+            Some(Ok(peer_id)) => match client.get_closest_peers(peer_id).await {
+                Ok(peers) => println!("kademlia,closestpeers,{}", peers.len()),
+                Err(error) => println!("error,{error}"),
+            },
+            Some(Err(_)) => println!("error,invalid peer id"),
+            None => println!("error,usage: /findpeers <peerid>"),
+        },
+        Some(command) if command.starts_with('/') => {
+            println!("error,unknown command {command}");
+        }
+        _ => {
+            if let Err(error) = client
+                .publish_chat("universal-connectivity".to_string(), line.to_string())
+                .await
+            {
+                println!("error,{error}");
+            }
+        }
+    }
+}